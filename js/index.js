@@ -33,6 +33,13 @@ const presets = {
             ["(== pa pa)", "true"],
         ],
     },
+    "Constant folding": {
+        program: "(* (+ 2 3) (- x x))",
+        rewriteRules: [
+            ["(- pa pa)", "0"],
+            ["(* pa 0)", "0"],
+        ],
+    },
     "Pset #4": {
         program: "(land x y (f (g (f z))) (h y x) (h w x))",
         rewriteRules: [
@@ -105,6 +112,41 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
     var in_graph = false;
     var runtime;
 
+    // Alt-clicking two e-classes one after the other merges them:
+    var union_pending = null;
+    vis_graph.on("click", function(params) {
+        if (!in_graph || !runtime || !params.event.srcEvent.altKey || params.nodes.length !== 1) {
+            union_pending = null;
+            return;
+        }
+        let node = window.vis_nodes.get(params.nodes[0]);
+        if (node.shape !== "circle") {
+            return;
+        }
+        if (union_pending === null) {
+            union_pending = node.id;
+            window.vis_nodes.update({ id: node.id, borderWidth: 5 });
+            return;
+        }
+        window.vis_nodes.update({ id: union_pending, borderWidth: 1 });
+        try {
+            runtime.union(union_pending, node.id);
+        } catch (ex) {
+            document.getElementById("footer").textContent = ex;
+        }
+        union_pending = null;
+        redrawGraph();
+    });
+
+    // Rewrite rules are passed to the runtime by name, such that their labels
+    // remain stable when other rules are added or removed:
+    var rule_counter = 0;
+
+    function newRuleName() {
+        rule_counter += 1;
+        return "r" + rule_counter;
+    }
+
     function applyPreset(presetName) {
         const preset = presets[presetName];
 
@@ -114,8 +156,9 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
         // Remove all defined rewrite rules and add rules from the preset:
         clear_rwr(null);
 
-        for (let [src, dest] of preset.rewriteRules) {
+        for (let [src, dest, direction = "→"] of preset.rewriteRules) {
             let row = document.createElement('div');
+            row.dataset.name = newRuleName();
             row.className = "row";
             row.style = "margin-left: 1em";
 
@@ -126,7 +169,7 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
             row.appendChild(src_p)
 
             let arrow = document.createElement('p');
-            arrow.textContent = "→";
+            arrow.textContent = direction;
             arrow.className = "col s1";
             arrow.style = "font-size: 20pt; margin-top: 0px";
             row.appendChild(arrow);
@@ -218,9 +261,16 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
         let rwr = document.getElementById("rwr");
         let rwrs = []
         for (var i = 1; i < rwr.children.length; i++) {
-            let left = rwr.children[i].children[0].textContent;
-            let right = rwr.children[i].children[2].textContent;
-            rwrs.push(left, right);
+            for (let side of [rwr.children[i].children[0], rwr.children[i].children[2]]) {
+                side.style.textDecoration = "";
+                side.removeAttribute("title");
+            }
+            rwrs.push({
+                name: rwr.children[i].dataset.name,
+                lhs: rwr.children[i].children[0].textContent,
+                direction: rwr.children[i].children[1].textContent,
+                rhs: rwr.children[i].children[2].textContent,
+            });
         }
         try {
             runtime = LispylangEggvizRuntime.new(program, rwrs);
@@ -231,8 +281,11 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
             } else {
                 start_reset();
                 let footer = document.getElementById("footer");
-                footer.style = "color: red";
-                footer.textContent = ex;
+                footer.style = "color: red; white-space: pre-line";
+                footer.textContent = ex.get("message");
+                for (let diagnostic of ex.get("diagnostics") || []) {
+                    showParseError(diagnostic);
+                }
             }
             return false;
         }
@@ -241,6 +294,37 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
     }
 
 
+    // Convert a byte offset into the UTF-8 encoding of `text`, as reported by
+    // the runtime, into an index of UTF-16 code units, as used by the DOM.
+    function utf16Index(text, byte_offset) {
+        let bytes = new TextEncoder().encode(text).subarray(0, byte_offset);
+        return new TextDecoder().decode(bytes).length;
+    }
+
+    // Point the user at the token a parse error refers to: select it in the
+    // program input, or underline the side of the offending rewrite rule.
+    function showParseError(ex) {
+        if (ex.get("severity") !== "error") {
+            return;
+        }
+        if (ex.get("context") === "program") {
+            let program = document.getElementById("program");
+            let start = utf16Index(program.value, ex.get("offset"));
+            let end = utf16Index(program.value, ex.get("offset") + ex.get("length"));
+            program.focus();
+            program.setSelectionRange(start, Math.max(end, start + 1));
+        } else if (ex.get("context") === "rewrite-rule") {
+            let rwr = document.getElementById("rwr");
+            for (var i = 1; i < rwr.children.length; i++) {
+                if ("rwr:" + rwr.children[i].dataset.name === ex.get("label")) {
+                    let side = rwr.children[i].children[ex.get("side") === "left" ? 0 : 2];
+                    side.style.textDecoration = "underline wavy red";
+                    side.title = ex.get("message");
+                }
+            }
+        }
+    }
+
     function firstGraph() {
         makeRuntime(true);
     }
@@ -251,10 +335,19 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
     }
 
     function prevGraph() {
-        // TODO implement backtracking
+        runtime.undo();
         redrawGraph();
     }
 
+    function highlightMatches(rule_name, highlight) {
+        for (let rule_match of runtime.preview_rule(rule_name)) {
+            window.vis_nodes.update({
+                id: rule_match.get("eclass"),
+                borderWidth: highlight ? 5 : 1,
+            });
+        }
+    }
+
     function clearGraph() {
         window.vis_nodes.clear();
         window.vis_edges.clear();
@@ -270,22 +363,37 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
 
         let class_nodes = new Map();
 
-        for (let [eclass_id, enodes] of graph) {
+        // Label e-classes with the names of the root terms they represent, to
+        // see which of them became equivalent:
+        let root_names = new Map();
+        for (let [name, eclass_id] of runtime.roots()) {
+            root_names.set(eclass_id, (root_names.get(eclass_id) || []).concat([name]));
+        }
+
+        for (let [eclass_id, eclass] of graph) {
+            let enodes = eclass.get("enodes");
+            let data = eclass.get("data");
+
             // Add square nodes for eclasses
             let vis_eclass = {
                 id: eclass_id,
-                label: "C" + eclass_id,
+                label: "C" + eclass_id
+                    + (data ? "\n" + data : "")
+                    + (root_names.has(eclass_id) ? "\n" + root_names.get(eclass_id).join(", ") : ""),
                 shape: "circle",
                 size: 50,
                 font: "30px sans-serif black",
                 group: Number(eclass_id),
+                borderWidth: 1,
             };
             window.vis_nodes.update(vis_eclass);
             processed_classes.add(vis_eclass.id);
             class_nodes[eclass_id] = vis_eclass.id;
 
             for (let [enode_id, enode] of enodes) {
-                let enode_id_str = String(enode_id);
+                // Prefix e-node ids, such that they don't collide with the
+                // ids of e-class nodes:
+                let enode_id_str = "n" + enode_id;
                 let function_label = enode.get("label");
                 let eclass_children = enode.get("children");
 
@@ -313,7 +421,7 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
                 processed_eclass_edges.add(vis_eclass_edge.id);
 
                 for (let i = 0; i < eclass_children.length; i++) {
-                    for (let [eclass_child_node_id, eclass_child_node] of graph.get(eclass_children[i])) {
+                    for (let [eclass_child_node_id, eclass_child_node] of graph.get(eclass_children[i]).get("enodes")) {
                         let vis_edge = {
                             id: enode_id_str + ":" + eclass_children[i] + "$" + i,
                             from: enode_id_str,
@@ -351,6 +459,7 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
 
     function add_rwr(e) {
         let elt = document.createElement('div');
+        elt.dataset.name = newRuleName();
         elt.className = "row";
         elt.style = "margin-left: 1em";
         let src = document.createElement('input');
@@ -360,7 +469,11 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
         let arrow = document.createElement('p');
         arrow.textContent = "→";
         arrow.className = "col s1";
-        arrow.style = "font-size: 20pt; margin-top: 0px";
+        arrow.style = "font-size: 20pt; margin-top: 0px; cursor: pointer";
+        arrow.title = "Click to toggle between a one-way and a bidirectional rule";
+        arrow.onclick = function(e) {
+            arrow.textContent = arrow.textContent === "→" ? "↔" : "→";
+        };
         elt.appendChild(arrow);
         let dest = document.createElement('input');
         dest.style = "font-family: monospace; font-size: 10pt";
@@ -409,6 +522,9 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
             dest_p.className = "col s5";
             dest_p.textContent = dest;
             let arrow = row.children[1];
+            arrow.onclick = null;
+            arrow.style.cursor = "";
+            arrow.removeAttribute("title");
             cfm.className = "red col";
             cfm.textContent = "⨯";
             row.replaceChildren(src_p, arrow, dest_p, cfm);
@@ -451,12 +567,16 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
             for (let i = 1; i < rwr.children.length; i++) {
                 rwr.children[i].onmouseenter = function(e) {
                     e.target.className += " green lighten-2";
+                    highlightMatches("rwr:" + rwr.children[i].dataset.name, true);
                 };
                 rwr.children[i].onmouseleave = function(e) {
                     e.target.className = e.target.className.replace(" green lighten-2", "");
+                    highlightMatches("rwr:" + rwr.children[i].dataset.name, false);
                 };
                 rwr.children[i].onclick = (e) => {
-                    let rule_name = "rwr#" + (i - 1);
+                    // Shift-click applies a bidirectional rule in reverse:
+                    let bidirectional = rwr.children[i].children[1].textContent === "↔";
+                    let rule_name = "rwr:" + rwr.children[i].dataset.name + (bidirectional && e.shiftKey ? "-rev" : "");
                     runtime.rewrite_rule(rule_name);
                     redrawGraph();
                 };
@@ -465,8 +585,11 @@ import("../pkg/index.js").catch(console.error).then(wasm_module => {
                 return;
             }
             let footer = document.getElementById("footer");
-            footer.style = "color: black";
-            footer.textContent = "Click on a rewrite rule to apply it, or click on the Auto button to apply all rewrite rules once. Click the back arrow to go back a step.";
+            footer.style = "color: black; white-space: pre-line";
+            footer.textContent = "Click on a rewrite rule to apply it (shift-click to apply a ↔ rule in reverse), alt-click two e-classes to merge them, or click on the Auto button to apply all rewrite rules once. Click the back arrow to go back a step.";
+            for (let diagnostic of runtime.diagnostics()) {
+                footer.textContent += "\n" + diagnostic.get("severity") + ": " + diagnostic.get("message");
+            }
         } else {
             // Remove graph and re-enable program/rwr panes
             clearGraph();
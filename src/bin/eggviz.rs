@@ -0,0 +1,302 @@
+//! Native command line interface to the eggviz runtime over the lispylang.
+//!
+//! Loads a program and a file of rewrite rules, applies a sequence of steps
+//! and prints the resulting e-graph, or exports it in the Graphviz DOT format.
+
+use std::num::NonZeroUsize;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use eggviz::lispylang::Program;
+use eggviz::script::{
+    parse_rewrite_rule, parse_rule_label, run_script_dir, significant_lines, EggvizScriptRule,
+};
+use eggviz::{EggvizRewriteRuleLabel, EggvizRunLimits, EggvizRuntime};
+
+const USAGE: &str = "\
+Usage: eggviz <program-file> <rules-file> [options] [steps]
+       eggviz --scripts <dir>
+
+Rewrite rules are given one per line, as `[name:] <lhs> <direction> <rhs>`,
+where the direction is `=>` or `<=>`. Empty lines and lines starting with `#`
+are ignored. Unnamed rules are labeled by their index (`rwr#N`).
+
+Steps, applied in order:
+  --rule <label>        Apply a single rewrite rule (`rwr:name`, `rwr#N` or
+                        just the rule's name)
+  --auto                Apply all rewrite rules once
+  --union <a> <b>       Merge two e-classes, given by their ids
+  --add-term <term>     Add a term to the e-graph
+  --saturate            Apply all rewrite rules until the e-graph is
+                        saturated or any of the limits is reached
+
+Options:
+  --iter-limit <n>      Iteration limit for --saturate (default: 30)
+  --node-limit <n>      E-node limit for --saturate (default: 10000)
+  --time-limit-ms <n>   Time limit for --saturate (default: 5000)
+  --dot <file>          Export the resulting e-graph in the Graphviz DOT
+                        format, highlighting the last step (`-` for stdout)
+  --quiet               Don't print the resulting e-graph
+  --help                Print this message
+
+With --scripts, every script (`*.eggviz`) in the directory is run instead,
+reporting each failing script along with the step it failed at.";
+
+enum Step {
+    Rule(EggvizRewriteRuleLabel),
+    Auto,
+    Union(egg::Id, egg::Id),
+    AddTerm(String),
+    Saturate,
+}
+
+struct Args {
+    program_file: String,
+    rules_file: String,
+    steps: Vec<Step>,
+    limits: EggvizRunLimits,
+    dot_file: Option<String>,
+    quiet: bool,
+}
+
+fn value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for option {}", option))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut steps = Vec::new();
+    let mut limits = EggvizRunLimits::default();
+    let mut dot_file = None;
+    let mut quiet = false;
+
+    let parse_number = |value: String, option: &str| {
+        value
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid value \"{}\" for option {}", value, option))
+    };
+    let parse_eclass = |value: String| {
+        value
+            .parse::<usize>()
+            .map(egg::Id::from)
+            .map_err(|_| format!("Unable to parse e-class id \"{}\"", value))
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rule" => {
+                steps.push(Step::Rule(parse_rule_label(&value(&mut args, &arg)?)));
+            }
+            "--auto" => steps.push(Step::Auto),
+            "--union" => {
+                let a = parse_eclass(value(&mut args, &arg)?)?;
+                let b = parse_eclass(value(&mut args, &arg)?)?;
+                steps.push(Step::Union(a, b));
+            }
+            "--add-term" => steps.push(Step::AddTerm(value(&mut args, &arg)?)),
+            "--saturate" => steps.push(Step::Saturate),
+            "--iter-limit" => {
+                limits.iter_limit = NonZeroUsize::new(parse_number(value(&mut args, &arg)?, &arg)?)
+                    .ok_or_else(|| "Iteration limit must be at least 1".to_string())?;
+            }
+            "--node-limit" => limits.node_limit = parse_number(value(&mut args, &arg)?, &arg)?,
+            "--time-limit-ms" => {
+                limits.time_limit =
+                    Duration::from_millis(parse_number(value(&mut args, &arg)?, &arg)? as u64);
+            }
+            "--dot" => dot_file = Some(value(&mut args, &arg)?),
+            "--quiet" => quiet = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+
+    match <[String; 2]>::try_from(positional) {
+        Ok([program_file, rules_file]) => Ok(Args {
+            program_file,
+            rules_file,
+            steps,
+            limits,
+            dot_file,
+            quiet,
+        }),
+        Err(_) => Err("Expected a program file and a rules file".to_string()),
+    }
+}
+
+fn parse_rules(rules: &str) -> Result<Vec<EggvizScriptRule>, String> {
+    significant_lines(rules)
+        .map(|(line, rule)| parse_rewrite_rule(rule).map_err(|e| format!("{} (line {})", e, line)))
+        .collect()
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let read = |path: &str| {
+        std::fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))
+    };
+    let program = read(&args.program_file)?;
+    let rules = parse_rules(&read(&args.rules_file)?)?;
+
+    let mut runtime = EggvizRuntime::<Program>::new(&program, rules).map_err(|e| e.to_string())?;
+    for diagnostic in runtime.diagnostics() {
+        eprintln!("{}: {}", diagnostic.severity, diagnostic);
+    }
+
+    // The runtime ignores unknown rewrite rules, reject them before applying
+    // any steps instead:
+    for step in args.steps.iter() {
+        if let Step::Rule(rule) = step {
+            runtime.preview_rule(rule).map_err(|e| e.to_string())?;
+        }
+    }
+
+    for (idx, step) in args.steps.into_iter().enumerate() {
+        let report = match step {
+            Step::Rule(rule) => runtime.rewrite_rule(rule),
+            Step::Auto => runtime.rewrite_auto(),
+            Step::Union(a, b) => runtime.union(a, b).map_err(|e| e.to_string())?,
+            Step::AddTerm(term) => {
+                let eclass = runtime.add_term(&term).map_err(|e| e.to_string())?;
+                println!("step {}: added {} to e-class {}", idx + 1, term, eclass);
+                continue;
+            }
+            Step::Saturate => {
+                let report = runtime.run_until_saturated(&args.limits);
+                println!(
+                    "step {}: stopped after {} iterations: {:?}",
+                    idx + 1,
+                    report.iterations.len(),
+                    report.stop_reason
+                );
+                report.step
+            }
+        };
+        println!(
+            "step {}: applied [{}]",
+            idx + 1,
+            report
+                .applied_rules
+                .iter()
+                .map(|rule| rule.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    if !args.quiet {
+        let mut cost_function = runtime.program_cost_function();
+        for (name, eclass) in runtime.roots() {
+            let extraction = runtime
+                .extract(Some(eclass), cost_function.as_mut())
+                .map_err(|e| e.to_string())?;
+            println!(
+                "root {} in e-class {}: {} (cost {})",
+                name, eclass, extraction.term, extraction.cost
+            );
+        }
+
+        let mut eclasses: Vec<_> = runtime.current_graph().into_iter().collect();
+        eclasses.sort_by_key(|(eclass, _)| eclass.parse::<usize>().ok());
+        for (eclass, (data, enodes)) in eclasses {
+            let mut enodes: Vec<_> = enodes.into_iter().collect();
+            enodes.sort();
+            let enodes: Vec<String> = enodes
+                .into_iter()
+                .map(|(_, (function, children))| {
+                    if children.is_empty() {
+                        function
+                    } else {
+                        format!("({} C{})", function, children.join(" C"))
+                    }
+                })
+                .collect();
+            println!(
+                "C{}{}{}: {}",
+                eclass,
+                if data.is_empty() { "" } else { " " },
+                data,
+                enodes.join(", ")
+            );
+        }
+    }
+
+    if let Some(dot_file) = args.dot_file {
+        let dot = runtime.dump_graph(true);
+        if dot_file == "-" {
+            print!("{}", dot);
+        } else {
+            std::fs::write(&dot_file, dot)
+                .map_err(|e| format!("Unable to write {}: {}", dot_file, e))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn run_scripts(dir: &str) -> Result<bool, String> {
+    let results = run_script_dir::<Program>(dir)
+        .map_err(|e| format!("Unable to run scripts in {}: {}", dir, e))?;
+
+    let mut failures = 0;
+    for (path, result) in &results {
+        match result {
+            Ok(()) => println!("{} ... ok", path.display()),
+            Err(e) => {
+                println!("{} ... FAILED at {}", path.display(), e);
+                failures += 1;
+            }
+        }
+    }
+    println!(
+        "{} scripts run: {} passed, {} failed",
+        results.len(),
+        results.len() - failures,
+        failures
+    );
+
+    Ok(failures == 0)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    if args.first().map(String::as_str) == Some("--scripts") {
+        return match args.as_slice() {
+            [_, dir] => match run_scripts(dir) {
+                Ok(true) => ExitCode::SUCCESS,
+                Ok(false) => ExitCode::FAILURE,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ExitCode::FAILURE
+                }
+            },
+            _ => {
+                eprintln!("Expected a single directory of scripts");
+                eprintln!("Run with --help for usage information.");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let args = match parse_args(args.into_iter()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Run with --help for usage information.");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
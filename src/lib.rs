@@ -1,16 +1,20 @@
 use std::cell::RefCell;
-use std::collections::{hash_map::DefaultHasher, HashMap, HashSet, LinkedList};
+use std::collections::{HashMap, HashSet, LinkedList};
 use std::fmt::Display;
 use std::fmt::Formatter;
-use std::hash::{Hash, Hasher};
+use std::hash::Hash;
 use std::num::NonZeroUsize;
 use std::rc::Rc;
 use std::str::FromStr;
+use std::time::Duration;
 
-use wasm_bindgen::prelude::*;
-use web_sys::console;
+pub mod lispylang;
+pub mod script;
+#[cfg(feature = "wasm")]
+mod wasm;
 
-mod lispylang;
+#[cfg(feature = "wasm")]
+pub use wasm::{startup, LispylangEggvizRuntime};
 
 /// Label identifying each rewrite rule defined over a program.
 ///
@@ -26,6 +30,29 @@ mod lispylang;
 pub enum EggvizRewriteRuleLabel {
     Supplied(String),
     Indexed(usize),
+    /// Reverse direction of an implicitly numbered bidirectional rewrite rule.
+    IndexedReverse(usize),
+    /// Pseudo-rule for e-classes merged manually through
+    /// [`EggvizRuntime::union`], rather than by applying a rewrite rule.
+    Union,
+    /// Pseudo-rule for e-classes merged by the program's
+    /// [`EggvizProgram::Analysis`], such as through constant folding.
+    Analysis,
+}
+
+impl EggvizRewriteRuleLabel {
+    /// Label of the reverse direction of a bidirectional rewrite rule.
+    pub fn reversed(&self) -> Self {
+        match self {
+            EggvizRewriteRuleLabel::Supplied(label) => {
+                EggvizRewriteRuleLabel::Supplied(format!("{}-rev", label))
+            }
+            EggvizRewriteRuleLabel::Indexed(idx) => EggvizRewriteRuleLabel::IndexedReverse(*idx),
+            EggvizRewriteRuleLabel::IndexedReverse(idx) => EggvizRewriteRuleLabel::Indexed(*idx),
+            EggvizRewriteRuleLabel::Union => EggvizRewriteRuleLabel::Union,
+            EggvizRewriteRuleLabel::Analysis => EggvizRewriteRuleLabel::Analysis,
+        }
+    }
 }
 
 impl Display for EggvizRewriteRuleLabel {
@@ -33,6 +60,9 @@ impl Display for EggvizRewriteRuleLabel {
         match self {
             EggvizRewriteRuleLabel::Supplied(label) => write!(f, "rwr:{}", label),
             EggvizRewriteRuleLabel::Indexed(idx) => write!(f, "rwr#{}", idx),
+            EggvizRewriteRuleLabel::IndexedReverse(idx) => write!(f, "rwr#{}-rev", idx),
+            EggvizRewriteRuleLabel::Union => write!(f, "union"),
+            EggvizRewriteRuleLabel::Analysis => write!(f, "analysis"),
         }
     }
 }
@@ -41,8 +71,19 @@ impl FromStr for EggvizRewriteRuleLabel {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some(label) = s.strip_prefix("rwr:") {
+        if s == "union" {
+            Ok(EggvizRewriteRuleLabel::Union)
+        } else if s == "analysis" {
+            Ok(EggvizRewriteRuleLabel::Analysis)
+        } else if let Some(label) = s.strip_prefix("rwr:") {
             Ok(EggvizRewriteRuleLabel::Supplied(label.to_string()))
+        } else if let Some(idx_str) = s
+            .strip_prefix("rwr#")
+            .and_then(|idx_str| idx_str.strip_suffix("-rev"))
+        {
+            Ok(EggvizRewriteRuleLabel::IndexedReverse(
+                idx_str.parse::<usize>().map_err(|_| ())?,
+            ))
         } else if let Some(idx_str) = s.strip_prefix("rwr#") {
             Ok(EggvizRewriteRuleLabel::Indexed(
                 idx_str.parse::<usize>().map_err(|_| ())?,
@@ -53,45 +94,203 @@ impl FromStr for EggvizRewriteRuleLabel {
     }
 }
 
-/// Parsing context (program or rewrite rule byte offset) for annotating error
-/// messages.
+/// Direction in which a rewrite rule may be applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EggvizRewriteDirection {
+    /// Rewrite from the left-hand to the right-hand side only (`=>`).
+    Forward,
+    /// Rewrite in both directions (`<=>`). Such a rule is expanded into two
+    /// rewrites, labeled as given and with the
+    /// [`reversed`](EggvizRewriteRuleLabel::reversed) label respectively.
+    Bidirectional,
+}
+
+impl Display for EggvizRewriteDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EggvizRewriteDirection::Forward => write!(f, "=>"),
+            EggvizRewriteDirection::Bidirectional => write!(f, "<=>"),
+        }
+    }
+}
+
+impl FromStr for EggvizRewriteDirection {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "=>" | "→" => Ok(EggvizRewriteDirection::Forward),
+            "<=>" | "↔" => Ok(EggvizRewriteDirection::Bidirectional),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Side of a rewrite rule a parse error occurred in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EggvizRewriteRuleSide {
+    Left,
+    Right,
+}
+
+impl Display for EggvizRewriteRuleSide {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EggvizRewriteRuleSide::Left => write!(f, "left"),
+            EggvizRewriteRuleSide::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// Parsing context (program or rewrite rule source position) for annotating
+/// error messages.
 #[derive(Clone, Debug)]
 pub enum EggvizProgramParseContext {
     Program {
         /// Byte offset in the passed string.
         offset: usize,
+
+        /// Length of the offending token in bytes.
+        length: usize,
+
+        /// Line of the offset, starting at 1.
+        line: usize,
+
+        /// Column of the offset in characters, starting at 1.
+        column: usize,
     },
     RewriteRule {
-        /// Rewrite rules are indexed as a pair of left & right rules. An even
-        /// number refers to the left, an odd to the right rule.
         label: EggvizRewriteRuleLabel,
 
+        /// Side of the rewrite rule the offset refers to.
+        side: EggvizRewriteRuleSide,
+
         /// Byte offset in the passed string.
         offset: usize,
+
+        /// Length of the offending token in bytes.
+        length: usize,
+
+        /// Line of the offset, starting at 1.
+        line: usize,
+
+        /// Column of the offset in characters, starting at 1.
+        column: usize,
     },
 }
 
-/// Program or rewrite rule parse error.
+impl EggvizProgramParseContext {
+    /// Context of a token at `offset` with `length` bytes in the program
+    /// `source`.
+    pub fn program(source: &str, offset: usize, length: usize) -> Self {
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        EggvizProgramParseContext::Program {
+            offset,
+            length,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    /// Re-attribute a context obtained from parsing one side of a rewrite
+    /// rule to that rule.
+    pub fn in_rewrite_rule(
+        self,
+        label: &EggvizRewriteRuleLabel,
+        side: EggvizRewriteRuleSide,
+    ) -> Self {
+        match self {
+            EggvizProgramParseContext::Program {
+                offset,
+                length,
+                line,
+                column,
+            } => EggvizProgramParseContext::RewriteRule {
+                label: label.clone(),
+                side,
+                offset,
+                length,
+                line,
+                column,
+            },
+            context @ EggvizProgramParseContext::RewriteRule { .. } => context,
+        }
+    }
+}
+
+/// Severity of a diagnostic reported while parsing a program or its rewrite
+/// rules. Only errors prevent an [`EggvizRuntime`] from being constructed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EggvizDiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+impl Display for EggvizDiagnosticSeverity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EggvizDiagnosticSeverity::Error => write!(f, "error"),
+            EggvizDiagnosticSeverity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Program or rewrite rule parse error (or, depending on its severity,
+/// warning).
 #[derive(Clone, Debug)]
 pub struct EggvizProgramParseError {
+    pub severity: EggvizDiagnosticSeverity,
     pub msg: String,
     pub context: Option<EggvizProgramParseContext>,
 }
 
 impl Display for EggvizProgramParseError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        self.msg.fmt(f)
+        match self.context {
+            None => self.msg.fmt(f),
+            Some(EggvizProgramParseContext::Program { line, column, .. }) => {
+                write!(f, "{} (at line {}, column {})", self.msg, line, column)
+            }
+            Some(EggvizProgramParseContext::RewriteRule {
+                ref label,
+                side,
+                line,
+                column,
+                ..
+            }) => write!(
+                f,
+                "{} (in the {}-hand side of rewrite rule {}, at line {}, column {})",
+                self.msg, side, label, line, column
+            ),
+        }
     }
 }
 
 impl EggvizProgramParseError {
     pub fn context_less_owned(msg: String) -> Self {
-        EggvizProgramParseError { msg, context: None }
+        EggvizProgramParseError {
+            severity: EggvizDiagnosticSeverity::Error,
+            msg,
+            context: None,
+        }
     }
 
     pub fn context_less(msg: impl AsRef<str>) -> Self {
         Self::context_less_owned(msg.as_ref().to_string())
     }
+
+    /// Downgrade this diagnostic to a warning.
+    pub fn into_warning(self) -> Self {
+        EggvizProgramParseError {
+            severity: EggvizDiagnosticSeverity::Warning,
+            ..self
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == EggvizDiagnosticSeverity::Error
+    }
 }
 
 /// Interface to a high-level representation of a rewrite rule related to a
@@ -104,9 +303,131 @@ pub trait EggvizRewriteRule {
     /// Obtain a string-representation of the right-hand side of the rewrite
     /// rule, to be parsed into an [`egg::Pattern`].
     fn right_to_egg(&self) -> String;
+
+    /// Obtain the side condition of the rewrite rule, if any. The rule is
+    /// only applied to matches for which the condition holds.
+    fn condition_to_egg(&self) -> Option<EggvizRewriteCondition> {
+        None
+    }
+}
+
+/// Side condition of a rewrite rule. Operands are string-representations of
+/// terms, to be parsed into [`egg::Pattern`]s and instantiated with the
+/// substitution of a match.
+#[derive(Clone, Debug)]
+pub enum EggvizRewriteCondition {
+    /// Both operands are represented in the same e-class.
+    Equal(String, String),
+    /// The operands are not represented in the same e-class. An operand
+    /// which is not represented in the e-graph at all is considered to be
+    /// different from any other operand.
+    NotEqual(String, String),
+    Not(Box<EggvizRewriteCondition>),
+    And(Vec<EggvizRewriteCondition>),
+    Or(Vec<EggvizRewriteCondition>),
+}
+
+/// [`EggvizRewriteCondition`] with its operands parsed into patterns,
+/// checked by egg through an [`egg::ConditionalApplier`].
+#[derive(Clone)]
+enum EggvizCondition<L: EggvizLanguage> {
+    Equal(egg::Pattern<L>, egg::Pattern<L>),
+    NotEqual(egg::Pattern<L>, egg::Pattern<L>),
+    Not(Box<EggvizCondition<L>>),
+    And(Vec<EggvizCondition<L>>),
+    Or(Vec<EggvizCondition<L>>),
+}
+
+impl<L: EggvizLanguage> EggvizCondition<L> {
+    fn from_egg(condition: &EggvizRewriteCondition) -> Result<Self, String> {
+        let pattern = |operand: &str| {
+            egg::Pattern::from_str(operand).map_err(|e| {
+                format!(
+                    "Egg reported an error while trying to parse the generated \
+                     condition operand {:?}: {:?}",
+                    operand, e
+                )
+            })
+        };
+        let all = |conditions: &[EggvizRewriteCondition]| {
+            conditions
+                .iter()
+                .map(Self::from_egg)
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(match condition {
+            EggvizRewriteCondition::Equal(l, r) => EggvizCondition::Equal(pattern(l)?, pattern(r)?),
+            EggvizRewriteCondition::NotEqual(l, r) => {
+                EggvizCondition::NotEqual(pattern(l)?, pattern(r)?)
+            }
+            EggvizRewriteCondition::Not(c) => EggvizCondition::Not(Box::new(Self::from_egg(c)?)),
+            EggvizRewriteCondition::And(cs) => EggvizCondition::And(all(cs)?),
+            EggvizRewriteCondition::Or(cs) => EggvizCondition::Or(all(cs)?),
+        })
+    }
+
+    /// Find the e-class representing the instantiation of `pattern`, without
+    /// adding anything to the e-graph.
+    fn lookup<N: egg::Analysis<L>>(
+        pattern: &egg::Pattern<L>,
+        egraph: &egg::EGraph<L, N>,
+        subst: &egg::Subst,
+    ) -> Option<egg::Id> {
+        let mut ids: Vec<egg::Id> = Vec::new();
+        for node in pattern.ast.as_ref() {
+            let id = match node {
+                egg::ENodeOrVar::Var(var) => *subst.get(*var)?,
+                egg::ENodeOrVar::ENode(enode) => {
+                    egraph.lookup(enode.clone().map_children(|child| ids[usize::from(child)]))?
+                }
+            };
+            ids.push(id);
+        }
+        ids.last().map(|id| egraph.find(*id))
+    }
+
+    fn holds<N: egg::Analysis<L>>(&self, egraph: &egg::EGraph<L, N>, subst: &egg::Subst) -> bool {
+        let equal = |l: &egg::Pattern<L>, r: &egg::Pattern<L>| match (
+            Self::lookup(l, egraph, subst),
+            Self::lookup(r, egraph, subst),
+        ) {
+            (Some(l), Some(r)) => l == r,
+            _ => false,
+        };
+        match self {
+            EggvizCondition::Equal(l, r) => equal(l, r),
+            EggvizCondition::NotEqual(l, r) => !equal(l, r),
+            EggvizCondition::Not(c) => !c.holds(egraph, subst),
+            EggvizCondition::And(cs) => cs.iter().all(|c| c.holds(egraph, subst)),
+            EggvizCondition::Or(cs) => cs.iter().any(|c| c.holds(egraph, subst)),
+        }
+    }
+}
+
+impl<L: EggvizLanguage, N: egg::Analysis<L>> egg::Condition<L, N> for EggvizCondition<L> {
+    fn check(&self, egraph: &mut egg::EGraph<L, N>, _eclass: egg::Id, subst: &egg::Subst) -> bool {
+        self.holds(egraph, subst)
+    }
+
+    fn vars(&self) -> Vec<egg::Var> {
+        match self {
+            EggvizCondition::Equal(l, r) | EggvizCondition::NotEqual(l, r) => {
+                let mut vars = l.vars();
+                vars.extend(r.vars());
+                vars
+            }
+            EggvizCondition::Not(c) => egg::Condition::<L, N>::vars(c.as_ref()),
+            EggvizCondition::And(cs) | EggvizCondition::Or(cs) => cs
+                .iter()
+                .flat_map(|c| egg::Condition::<L, N>::vars(c))
+                .collect(),
+        }
+    }
 }
 
-pub trait EggvizLanguage: egg::Language + egg::FromOp + Hash + Send + Sync + 'static {
+pub trait EggvizLanguage:
+    egg::Language + egg::FromOp + Display + Hash + Send + Sync + 'static
+{
     /// Get the function or constant name represented by this language node:
     fn get_function_name(&self) -> &str;
 }
@@ -117,7 +438,7 @@ pub trait EggvizProgram {
     /// [`egg::Language`] type associated with this type.
     ///
     /// The program is expected to generate an egg-compatible string
-    /// representation of the program with [`EggvizProgram::to_egg`].
+    /// representation of the program with [`EggvizProgram::roots_to_egg`].
     /// [`EggvizProgram::Language`] will then be used to operate on the terms
     /// produced by this method and parsed by egg.
     type Language: EggvizLanguage;
@@ -146,19 +467,41 @@ pub trait EggvizProgram {
     /// make abritrary clones of this type and, when re-parsing rewrite rules
     /// but not the full program, pass an earlier version of the state into
     /// subsequent calls to [`EggvizProgram::parse_rewrite_rule`].
-    type ParseState: Clone;
+    ///
+    /// The state starts out as its [`Default`] and is filled in by
+    /// [`EggvizProgram::parse_str`]. It is retained even if the program fails
+    /// to parse, such that rewrite rules can still be checked against as much
+    /// of the program as could be parsed.
+    type ParseState: Clone + Default;
+
+    /// [`egg::Analysis`] maintained over the e-graph of this program.
+    ///
+    /// Programs not requiring an analysis can use `()`. The analysis is
+    /// constructed from the parsed program with [`EggvizProgram::analysis`],
+    /// its data is shown alongside each e-class through
+    /// [`EggvizProgram::analysis_data_to_string`].
+    type Analysis: egg::Analysis<Self::Language> + Clone + Default;
 
     /// Try to parse an [`EggvizProgram`] from a string.
     ///
     /// The string should contain all information necessary to construct a
     /// program of the given [`egg::Language`]. This information is further
-    /// accessible when constructing rewrite rules.
-    fn parse_str(input: &str) -> Result<(Self, Self::ParseState), EggvizProgramParseError>
+    /// accessible when constructing rewrite rules, through the passed
+    /// [`Self::ParseState`].
+    ///
+    /// Implementors should report as many diagnostics as possible, rather than
+    /// stopping at the first error. Warnings may be reported alongside a
+    /// successfully parsed program.
+    fn parse_str(
+        input: &str,
+        parse_state: &mut Self::ParseState,
+        diagnostics: &mut Vec<EggvizProgramParseError>,
+    ) -> Option<Self>
     where
         Self: Sized;
 
-    /// Given a parsed [`EggvizProgram`], try to parse and interpret rewrite
-    /// rules.
+    /// Given the state of parsing an [`EggvizProgram`], try to parse and
+    /// interpret rewrite rules.
     ///
     /// This method is further responsible for ensuring that the rewrite rules
     /// are valid given the parsed program. This means that in eggviz, rewrite
@@ -166,19 +509,192 @@ pub trait EggvizProgram {
     /// specific to a program. For instance, the method implementation should
     /// ensure that a rewrite rule containing functions respects the arity (and
     /// potentially types) of these functions as declared in the program.
+    ///
+    /// As with [`EggvizProgram::parse_str`], diagnostics are collected into
+    /// `diagnostics`, and [`None`] is returned if any of them is an error.
     fn parse_rewrite_rule(
-        &self,
         parse_state: &mut Self::ParseState,
         label: &EggvizRewriteRuleLabel,
         left: &str,
         right: &str,
-    ) -> Result<Self::RewriteRule, EggvizProgramParseError>;
+        diagnostics: &mut Vec<EggvizProgramParseError>,
+    ) -> Option<Self::RewriteRule>;
 
-    /// Dump the program as a recursive expression, to be parsed into a
-    /// [`egg::RecExpr`].
+    /// Try to parse a single term (without generic variables) in the context
+    /// of this program, for instance to query the e-graph for it.
     ///
-    ///
-    fn to_egg(&self) -> String;
+    /// Similar to [`EggvizProgram::roots_to_egg`], this returns a string
+    /// representation to be parsed into a [`egg::RecExpr`]. Implementors
+    /// should validate the term against the passed [`Self::ParseState`],
+    /// in the same way as for [`EggvizProgram::parse_rewrite_rule`].
+    fn parse_term(
+        &self,
+        parse_state: &mut Self::ParseState,
+        input: &str,
+    ) -> Result<String, EggvizProgramParseError>;
+
+    /// Cost function reflecting the costs of function symbols as specified in
+    /// the program. Defaults to [`egg::AstSize`].
+    fn cost_function(
+        &self,
+        _parse_state: &Self::ParseState,
+    ) -> Box<dyn EggvizCostFunction<Self::Language>> {
+        Box::new(egg::AstSize)
+    }
+
+    /// Construct the [`Self::Analysis`] for the e-graph of this program.
+    /// Defaults to the analysis' [`Default`].
+    fn analysis(&self, _parse_state: &Self::ParseState) -> Self::Analysis {
+        Self::Analysis::default()
+    }
+
+    /// String representation of an e-class' analysis data. Defaults to its
+    /// [`Debug`](std::fmt::Debug) representation.
+    fn analysis_data_to_string(
+        data: &<Self::Analysis as egg::Analysis<Self::Language>>::Data,
+    ) -> String {
+        format!("{:?}", data)
+    }
+
+    /// Dump the program's named root terms as recursive expressions, each to
+    /// be parsed into a [`egg::RecExpr`] and added to the e-graph. Names must
+    /// be unique, and there must be at least one root term.
+    fn roots_to_egg(&self) -> Vec<(String, String)>;
+}
+
+/// Single match of a rewrite rule's left-hand side in the e-graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EggvizRuleMatch {
+    /// Rewrite rule which matched.
+    pub rule: EggvizRewriteRuleLabel,
+
+    /// E-class in which the left-hand side of the rule matched.
+    pub eclass: egg::Id,
+
+    /// Pattern variables of the rule, along with the e-class they are bound
+    /// to for this match.
+    pub substitution: Vec<(String, egg::Id)>,
+}
+
+impl EggvizRuleMatch {
+    fn substitution(vars: &[egg::Var], subst: &egg::Subst) -> Vec<(String, egg::Id)> {
+        vars.iter()
+            .filter_map(|var| subst.get(*var).map(|id| (var.to_string(), *id)))
+            .collect()
+    }
+
+    fn from_search_matches<L: egg::Language, N: egg::Analysis<L>>(
+        rule: &EggvizRewriteRuleLabel,
+        rewrite: &egg::Rewrite<L, N>,
+        matches: &[egg::SearchMatches<L>],
+    ) -> Vec<Self> {
+        let vars = rewrite.searcher.vars();
+        matches
+            .iter()
+            .flat_map(|m| {
+                m.substs.iter().map(|subst| EggvizRuleMatch {
+                    rule: rule.clone(),
+                    eclass: m.eclass,
+                    substitution: Self::substitution(&vars, subst),
+                })
+            })
+            .collect()
+    }
+
+    /// Select only this match out of the matches found by egg for the
+    /// corresponding rewrite rule.
+    fn select<'a, L: egg::Language, N: egg::Analysis<L>>(
+        &self,
+        rewrite: &egg::Rewrite<L, N>,
+        matches: Vec<egg::SearchMatches<'a, L>>,
+    ) -> Vec<egg::SearchMatches<'a, L>> {
+        let vars = rewrite.searcher.vars();
+        for m in matches.into_iter().filter(|m| m.eclass == self.eclass) {
+            if let Some(subst) = m
+                .substs
+                .into_iter()
+                .find(|subst| Self::substitution(&vars, subst) == self.substitution)
+            {
+                return vec![egg::SearchMatches {
+                    eclass: m.eclass,
+                    substs: vec![subst],
+                    ast: m.ast,
+                }];
+            }
+        }
+
+        Vec::new()
+    }
+}
+
+/// Limits for a single invocation of the [`egg::Runner`] through the
+/// [`EggvizSingleStepSchedulerState`].
+///
+/// In contrast to the limits of the [`egg::Runner`] itself, these limits are
+/// not cumulative over all invocations of the runner.
+#[derive(Clone, Debug)]
+pub struct EggvizRunLimits {
+    /// Maximum number of iterations to run.
+    pub iter_limit: NonZeroUsize,
+
+    /// Maximum number of e-nodes in the e-graph.
+    pub node_limit: usize,
+
+    /// Maximum time to run for. Egg measures time through the `instant` crate,
+    /// which is supported on `wasm32-unknown-unknown`.
+    pub time_limit: Duration,
+}
+
+impl EggvizRunLimits {
+    /// Run exactly `iters` iterations, regardless of the size of the e-graph
+    /// and the time taken.
+    pub fn iterations(iters: NonZeroUsize) -> Self {
+        EggvizRunLimits {
+            iter_limit: iters,
+            node_limit: usize::MAX,
+            time_limit: Duration::MAX,
+        }
+    }
+}
+
+impl Default for EggvizRunLimits {
+    /// Default limits of [`egg::Runner`].
+    fn default() -> Self {
+        EggvizRunLimits {
+            iter_limit: NonZeroUsize::new(30).unwrap(),
+            node_limit: 10_000,
+            time_limit: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Statistics of a single rewrite rule, accumulated over all steps performed
+/// through the [`EggvizSingleStepSchedulerState`].
+#[derive(Clone, Debug, Default)]
+pub struct EggvizRuleStats {
+    /// Number of times the rule was searched for in the e-graph.
+    pub searches: usize,
+
+    /// Total number of matches found over all searches.
+    pub matches: usize,
+
+    /// Number of times applying the rule changed the e-graph.
+    pub applications: usize,
+
+    /// Net change of the number of e-nodes by applying the rule. Like
+    /// `classes_added`, this is measured right after applying the rule and
+    /// before the e-graph is rebuilt, so e-nodes which only become congruent
+    /// (and are deduplicated) during the rebuild are still counted.
+    pub nodes_added: usize,
+
+    /// Net change of the number of e-classes by applying the rule, measured
+    /// before the e-graph is rebuilt. This is negative if the rule merged more
+    /// e-classes than it added.
+    pub classes_added: isize,
+
+    /// Time spent searching for and applying the rule, in seconds.
+    pub search_time: f64,
+    pub apply_time: f64,
 }
 
 pub struct EggvizSingleStepSchedulerState(Rc<RefCell<EggvizSingleStepSchedulerInnerState>>);
@@ -186,41 +702,65 @@ pub struct EggvizSingleStepSchedulerState(Rc<RefCell<EggvizSingleStepSchedulerIn
 pub struct EggvizSingleStepSchedulerInnerState {
     target_iteration: usize,
     rewrite_target: Option<EggvizRewriteRuleLabel>,
+    match_target: Option<EggvizRuleMatch>,
     applied_rules: LinkedList<EggvizRewriteRuleLabel>,
+    matches: Vec<EggvizRuleMatch>,
+    rule_stats: HashMap<EggvizRewriteRuleLabel, EggvizRuleStats>,
 }
 
 impl EggvizSingleStepSchedulerState {
-    pub fn rewrite<'a, L: egg::Language + 'a, IterData: egg::IterationData<L, ()>>(
+    /// Statistics of all rewrite rules searched for or applied so far.
+    pub fn rule_stats(&self) -> HashMap<EggvizRewriteRuleLabel, EggvizRuleStats> {
+        self.0.borrow().rule_stats.clone()
+    }
+
+    pub fn rewrite<'a, L, N, IterData>(
         &self,
-        runner: &mut egg::Runner<L, (), IterData>,
-        rewrite_rules: impl IntoIterator<Item = &'a egg::Rewrite<L, ()>>,
-        iters: NonZeroUsize,
+        runner: &mut egg::Runner<L, N, IterData>,
+        rewrite_rules: impl IntoIterator<Item = &'a egg::Rewrite<L, N>>,
+        limits: &EggvizRunLimits,
         rule: Option<EggvizRewriteRuleLabel>,
-    ) -> LinkedList<EggvizRewriteRuleLabel> {
+    ) -> (LinkedList<EggvizRewriteRuleLabel>, Vec<EggvizRuleMatch>)
+    where
+        L: egg::Language + 'a,
+        N: egg::Analysis<L> + Default + 'a,
+        IterData: egg::IterationData<L, N>,
+    {
         // Set the rule to apply in the state, and apply it only in the next
-
-        // iteration (enforced for the first iteration by wrapping from
-        // usize::MAX to 0):
+        // `iter_limit` iterations:
         {
             let mut state = self.0.borrow_mut();
             state.rewrite_target = rule;
-            let latest_iteration = runner.iterations.len().wrapping_sub(1);
-            state.target_iteration = latest_iteration.wrapping_add(iters.get());
+            state.target_iteration = runner
+                .iterations
+                .len()
+                .saturating_add(limits.iter_limit.get() - 1);
         }
 
         // Get ownership of the runner by swapping it out with a default value.
         // TODO: we should seek to optimize this probably?
-        let owned_runner = std::mem::replace(runner, egg::Runner::new(()));
-
-        // Set an instance based on our state as the runner's scheduler. We
-        // don't have a way to retain the previous scheduler (currently), so
-        // this is a destructive operation:
-        let mut owned_runner = owned_runner.with_scheduler(EggvizSingleStepScheduler(
-            EggvizSingleStepSchedulerState(Rc::clone(&self.0)),
-        ));
-
-        // Now, reset any previous stop reason, otherwise egg with panic:
-        owned_runner.stop_reason = None;
+        let prev_runner = std::mem::replace(runner, egg::Runner::new(N::default()));
+
+        // Egg's limits are cumulative over all invocations of a runner, and
+        // can't be reset. Hence, move the e-graph and all other state into a
+        // fresh runner with our limits. This also sets an instance based on our
+        // state as the runner's scheduler. We don't have a way to retain the
+        // previous scheduler (currently), so this is a destructive operation:
+        let mut owned_runner = egg::Runner::new(N::default())
+            .with_egraph(prev_runner.egraph)
+            .with_iter_limit(
+                prev_runner
+                    .iterations
+                    .len()
+                    .saturating_add(limits.iter_limit.get()),
+            )
+            .with_node_limit(limits.node_limit)
+            .with_time_limit(limits.time_limit)
+            .with_scheduler(EggvizSingleStepScheduler(EggvizSingleStepSchedulerState(
+                Rc::clone(&self.0),
+            )));
+        owned_runner.iterations = prev_runner.iterations;
+        owned_runner.roots = prev_runner.roots;
 
         // Actually perform the rewrites.
         let mut owned_runner = owned_runner.run(rewrite_rules);
@@ -231,24 +771,30 @@ impl EggvizSingleStepSchedulerState {
         {
             let mut state = self.0.borrow_mut();
 
-            // Swap the list of applied rules out to return and clear it:
-            let mut applied_rules = LinkedList::new();
-            std::mem::swap(&mut applied_rules, &mut state.applied_rules);
+            // Swap the list of applied rules and their matches out to return
+            // and clear them:
+            let applied_rules = std::mem::take(&mut state.applied_rules);
+            let matches = std::mem::take(&mut state.matches);
 
-            applied_rules
+            (applied_rules, matches)
         }
     }
 
-    pub fn rewrite_rule<'a, L: egg::Language + 'a, IterData: egg::IterationData<L, ()>>(
+    pub fn rewrite_rule<'a, L, N, IterData>(
         &self,
-        runner: &mut egg::Runner<L, (), IterData>,
-        rewrite_rules: impl IntoIterator<Item = &'a egg::Rewrite<L, ()>>,
+        runner: &mut egg::Runner<L, N, IterData>,
+        rewrite_rules: impl IntoIterator<Item = &'a egg::Rewrite<L, N>>,
         rule: EggvizRewriteRuleLabel,
-    ) -> bool {
-        let applied_rules = self.rewrite(
+    ) -> (bool, Vec<EggvizRuleMatch>)
+    where
+        L: egg::Language + 'a,
+        N: egg::Analysis<L> + Default + 'a,
+        IterData: egg::IterationData<L, N>,
+    {
+        let (applied_rules, matches) = self.rewrite(
             runner,
             rewrite_rules,
-            NonZeroUsize::new(1).unwrap(),
+            &EggvizRunLimits::iterations(NonZeroUsize::new(1).unwrap()),
             Some(rule.clone()),
         );
 
@@ -260,7 +806,28 @@ impl EggvizSingleStepSchedulerState {
             .count();
 
         assert!(rule_count < 2);
-        rule_count > 0
+        (rule_count > 0, matches)
+    }
+
+    pub fn rewrite_rule_match<'a, L, N, IterData>(
+        &self,
+        runner: &mut egg::Runner<L, N, IterData>,
+        rewrite_rules: impl IntoIterator<Item = &'a egg::Rewrite<L, N>>,
+        rule_match: EggvizRuleMatch,
+    ) -> (bool, Vec<EggvizRuleMatch>)
+    where
+        L: egg::Language + 'a,
+        N: egg::Analysis<L> + Default + 'a,
+        IterData: egg::IterationData<L, N>,
+    {
+        // Restrict the scheduler to only apply this single match of the rule:
+        let rule = rule_match.rule.clone();
+        self.0.borrow_mut().match_target = Some(rule_match);
+
+        let res = self.rewrite_rule(runner, rewrite_rules, rule);
+
+        self.0.borrow_mut().match_target = None;
+        res
     }
 }
 
@@ -281,7 +848,10 @@ impl EggvizSingleStepScheduler {
         EggvizSingleStepSchedulerState(Rc::new(RefCell::new(EggvizSingleStepSchedulerInnerState {
             target_iteration: 0,
             rewrite_target: None,
+            match_target: None,
             applied_rules: LinkedList::new(),
+            matches: Vec::new(),
+            rule_stats: HashMap::new(),
         })))
     }
 }
@@ -295,23 +865,31 @@ impl<L: egg::Language, N: egg::Analysis<L>> egg::RewriteScheduler<L, N>
         egraph: &egg::EGraph<L, N>,
         rewrite: &'a egg::Rewrite<L, N>,
     ) -> Vec<egg::SearchMatches<'a, L>> {
+        let rewrite_label = EggvizRewriteRuleLabel::from_str(rewrite.name.as_str()).unwrap();
+
         // Get a mutable reference to the current state. The state must not be
         // borrowed anywhere while egg is running!
-        let state = self.0 .0.borrow();
+        let mut state = self.0 .0.borrow_mut();
 
         if iteration > state.target_iteration {
             Vec::new()
         } else if state
             .rewrite_target
             .as_ref()
-            .map(|target| {
-                EggvizRewriteRuleLabel::from_str(rewrite.name.as_str()).unwrap() == *target
-            })
+            .map(|target| rewrite_label == *target)
             // If we don't have a target rewrite rule to search for,
             // apply all rules unconditionally:
             .unwrap_or(true)
         {
-            rewrite.search(egraph)
+            let start = instant::Instant::now();
+            let matches = rewrite.search(egraph);
+
+            let stats = state.rule_stats.entry(rewrite_label).or_default();
+            stats.searches += 1;
+            stats.matches += matches.iter().map(|m| m.substs.len()).sum::<usize>();
+            stats.search_time += start.elapsed().as_secs_f64();
+
+            matches
         } else {
             Vec::new()
         }
@@ -333,6 +911,12 @@ impl<L: egg::Language, N: egg::Analysis<L>> egg::RewriteScheduler<L, N>
         // borrowed anywhere while egg is running!
         let mut state = self.0 .0.borrow_mut();
 
+        // When targeting a single match of the rule, discard all others:
+        let matches = match state.match_target {
+            Some(ref target) => target.select(rewrite, matches),
+            None => matches,
+        };
+
         if iteration > state.target_iteration {
             0
         } else if matches.len() > 0
@@ -349,317 +933,2312 @@ impl<L: egg::Language, N: egg::Analysis<L>> egg::RewriteScheduler<L, N>
                 egraph.classes().filter(|eclass| !eclass.is_empty()).count(),
             );
 
+            // Record the individual matches, to be reported back:
+            state.matches.extend(EggvizRuleMatch::from_search_matches(
+                &rewrite_label,
+                rewrite,
+                &matches,
+            ));
+
             // Apply the rewrite rules to the graph:
-            rewrite.apply(egraph, &matches);
+            let start = instant::Instant::now();
+            let changed_classes = rewrite.apply(egraph, &matches);
+            let apply_time = start.elapsed().as_secs_f64();
 
             let (new_nodes, new_classes) = (
                 egraph.total_number_of_nodes(),
                 egraph.classes().filter(|eclass| !eclass.is_empty()).count(),
             );
 
-            // Add the rule (and how many nodes it affected) into the
-            // list of applied rules:
+            // Record the rule's statistics, and add the rule into the list of
+            // applied rules if it affected any nodes:
+            let state = &mut *state;
+            let stats = state.rule_stats.entry(rewrite_label.clone()).or_default();
+            stats.apply_time += apply_time;
             if prev_nodes != new_nodes || prev_classes != new_classes {
+                stats.applications += 1;
+                stats.nodes_added += new_nodes.saturating_sub(prev_nodes);
+                stats.classes_added += new_classes as isize - prev_classes as isize;
                 state.applied_rules.push_back(rewrite_label);
-            }
 
-            matches.len()
+                // Like egg's default scheduler, report the number of e-classes
+                // changed, not the number of matches. Egg only considers the
+                // e-graph saturated if no rule changed anything:
+                changed_classes.len()
+            } else {
+                0
+            }
         } else {
             0
         }
     }
 }
 
+/// Rewrite rule used in a single step of an [`EggvizExplanation`].
 #[derive(Clone, Debug)]
-pub enum EggvizRuntimeError {
-    ParseError(EggvizProgramParseError),
-    DuplicateRewriteRuleLabel(EggvizRewriteRuleLabel),
-    InternalError(String),
-}
-
-impl Display for EggvizRuntimeError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        match self {
-            EggvizRuntimeError::ParseError(e) => format!("Parsing Error: {}", e).fmt(f),
-            EggvizRuntimeError::DuplicateRewriteRuleLabel(l) => {
-                format!("Error: Rewrite rule `{}` was duplicated.", l).fmt(f)
-            }
-            EggvizRuntimeError::InternalError(e) => format!("Internal Error: {}", e).fmt(f),
-        }
-    }
-}
+pub struct EggvizExplanationRule {
+    pub label: EggvizRewriteRuleLabel,
 
-pub struct EggvizRuntime<P: EggvizProgram> {
-    rewrite_rules: Vec<egg::Rewrite<<P as EggvizProgram>::Language, ()>>,
-    sched_state: EggvizSingleStepSchedulerState,
-    runner: egg::Runner<P::Language, ()>,
+    /// Whether the rule was applied from its left- to its right-hand side, or
+    /// the other way around.
+    pub forward: bool,
 }
 
-impl<P: EggvizProgram> EggvizRuntime<P> {
-    pub fn new(
-        program_str: impl AsRef<str>,
-        rewrite_rules_str: impl IntoIterator<
-            Item = (Option<impl AsRef<str>>, impl AsRef<str>, impl AsRef<str>),
-        >,
-    ) -> Result<Self, EggvizRuntimeError> {
-        // Try to construct a program from the given string, returning any parse
-        // errors to the caller. Further ensure that the context doesn't lie
-        // about the parsing error type.
-        let (program, mut parse_state) =
-            P::parse_str(program_str.as_ref()).map_err(|e| match e.context {
-                Some(EggvizProgramParseContext::RewriteRule { .. }) => {
-                    EggvizRuntimeError::InternalError(format!(
-                        "Invalid parse error context for parsing program: {:?}",
-                        e
-                    ))
-                }
-                _ => EggvizRuntimeError::ParseError(e),
-            })?;
-
-        // With the program constructed, now try to parse the passed rewrite
-        // rules in the program's context. This also performs a sanity check
-        // that the assigned labels are unique. We require this for our
-        // single-step scheduler:
-        let mut rewrite_rule_labels: HashSet<EggvizRewriteRuleLabel> = HashSet::new();
-        let rewrite_rules = rewrite_rules_str
-            .into_iter()
-            .enumerate()
-            .map(|(idx, (opt_str_label, left, right))| {
-                if let Some(str_label) = opt_str_label {
-                    (
-                        EggvizRewriteRuleLabel::Supplied(str_label.as_ref().to_string()),
-                        left,
-                        right,
-                    )
-                } else {
-                    (EggvizRewriteRuleLabel::Indexed(idx), left, right)
-                }
-            })
-            .map(|(rwr_label, left, right)| {
-                if !rewrite_rule_labels.insert(rwr_label.clone()) {
-                    Err(EggvizRuntimeError::DuplicateRewriteRuleLabel(rwr_label))
-                } else {
-                    Ok((rwr_label, left, right))
-                }
-            })
-            .map(|res| {
-                res.and_then(|(rwr_label, left, right)| {
-                    let rewrite_rule = program
-                        .parse_rewrite_rule(
-                            &mut parse_state,
-                            &rwr_label,
-                            left.as_ref(),
-                            right.as_ref(),
-                        )
-                        .map_err(|e| match e.context {
-                            Some(EggvizProgramParseContext::RewriteRule { ref label, .. })
-                                if *label != rwr_label =>
-                            {
-                                EggvizRuntimeError::InternalError(format!(
-                                "Invalid parse error context for parsing rewrite rule {:?}: {:?}",
-                                &rwr_label, e
-                            ))
-                            }
-                            _ => EggvizRuntimeError::ParseError(e),
-                        })?;
-
-                    Ok((rwr_label, rewrite_rule))
-                })
-            })
-            .map(|res: Result<_, EggvizRuntimeError>| {
-                res.and_then(|(rwr_label, rewrite_rule)| {
-                    egg::Rewrite::new(
-                        rwr_label.to_string(),
-                        egg::Pattern::from_str(&rewrite_rule.left_to_egg()).map_err(|e| {
-                            EggvizRuntimeError::InternalError(format!(
-                                "Egg reported an error while trying to parse the \
-                                 generated left-hand rewrite rule expression for \
-                                 rule {:?}: {:?}",
-                                rwr_label, e
-                            ))
-                        })?,
-                        egg::Pattern::from_str(&rewrite_rule.right_to_egg()).map_err(|e| {
-                            EggvizRuntimeError::InternalError(format!(
-                                "Egg reported an error while trying to parse the \
-                                 generated right-hand rewrite rule expression for \
-                                 rule {:?}: {:?}",
-                                rwr_label, e
-                            ))
-                        })?,
-                    )
-                    .map_err(|e| {
+impl EggvizExplanationRule {
+    fn from_egg(
+        forward_rule: Option<egg::Symbol>,
+        backward_rule: Option<egg::Symbol>,
+    ) -> Result<Option<Self>, EggvizRuntimeError> {
+        forward_rule
+            .map(|rule| (rule, true))
+            .or_else(|| backward_rule.map(|rule| (rule, false)))
+            .map(|(rule, forward)| {
+                EggvizRewriteRuleLabel::from_str(rule.as_str())
+                    .map(|label| EggvizExplanationRule { label, forward })
+                    .map_err(|()| {
                         EggvizRuntimeError::InternalError(format!(
-                            "Egg reported an error while constructing a rewrite \
-                             at {:?}: {:?}",
-                            rwr_label, e,
+                            "Egg reported an explanation step with an unknown rule {:?}",
+                            rule
                         ))
                     })
-                })
             })
-            .collect::<Result<Vec<egg::Rewrite<_, _>>, EggvizRuntimeError>>()?;
+            .transpose()
+    }
+}
 
-        // Now, convert the program into an egg expression, annotated with the
-        // type of the language we're using:
-        let expr: egg::RecExpr<P::Language> = program.to_egg().parse().map_err(|e| {
-            EggvizRuntimeError::InternalError(format!(
-                "Egg reported error while trying to parse the generated program expression: {:?}",
-                e
-            ))
-        })?;
+/// Single term in the flat (sequential) representation of an
+/// [`EggvizExplanation`].
+#[derive(Clone, Debug)]
+pub struct EggvizExplanationStep {
+    /// Rewrite rule used to obtain this term from the previous one. This is
+    /// `None` for the first term in the sequence.
+    pub rule: Option<EggvizExplanationRule>,
 
-        // Finally, we create an instance of our single-step scheduler. This
-        // scheduler allows our runtime to retain control over egg's behavior
-        // while we kick off control to egg's Runner. We share state with this
-        // scheduler to provide instructions and collect responses.
-        let sched_state = EggvizSingleStepScheduler::initial_state();
+    /// Egg string representation of the term.
+    pub term: String,
+}
 
-        // Piece it all together in an instance of egg's Runner (the scheduler
-        // is set later implicitly by `rewrite_rule()`:
-        let runner = egg::Runner::default()
-            .with_expr(&expr)
-            // required, the default timeout is 5sec which would make an
-            // instance of EggvizRuntime unusable after that
-            .with_time_limit(std::time::Duration::MAX);
+/// Node in the tree representation of an [`EggvizExplanation`].
+#[derive(Clone, Debug)]
+pub struct EggvizExplanationTree {
+    /// Function or constant name of the term's root.
+    pub function: String,
 
-        Ok(EggvizRuntime {
-            rewrite_rules,
-            sched_state,
-            runner,
+    /// Rewrite rule used to obtain this term from the previous one in its
+    /// sequence of proofs, if any.
+    pub rule: Option<EggvizExplanationRule>,
+
+    /// For each child of the term, a sequence of proofs showing its
+    /// equivalence to the respective child of the next term in the sequence.
+    pub child_proofs: Vec<Vec<EggvizExplanationTree>>,
+}
+
+impl EggvizExplanationTree {
+    fn from_egg<L: EggvizLanguage>(
+        tree_term: &Rc<egg::TreeTerm<L>>,
+    ) -> Result<Self, EggvizRuntimeError> {
+        Ok(EggvizExplanationTree {
+            function: tree_term.node.get_function_name().to_string(),
+            rule: EggvizExplanationRule::from_egg(tree_term.forward_rule, tree_term.backward_rule)?,
+            child_proofs: tree_term
+                .child_proofs
+                .iter()
+                .map(|child_proof| {
+                    child_proof
+                        .iter()
+                        .map(Self::from_egg)
+                        .collect::<Result<Vec<_>, EggvizRuntimeError>>()
+                })
+                .collect::<Result<Vec<_>, _>>()?,
         })
     }
+}
 
-    pub fn rewrite_rule(&mut self, rule: EggvizRewriteRuleLabel) -> bool {
-        self.sched_state
-            .rewrite_rule(&mut self.runner, self.rewrite_rules.iter(), rule)
-    }
+/// Explanation (proof) of the equivalence of two terms, as a sequence of
+/// rewrite rule applications.
+#[derive(Clone, Debug)]
+pub struct EggvizExplanation {
+    /// Sequence of terms, starting with the left and ending with the right
+    /// term, each obtained from the previous one by a single rewrite.
+    pub flat: Vec<EggvizExplanationStep>,
+
+    /// Tree representation of the proof, where rewrites of subterms are nested
+    /// into the proof of their parent term.
+    pub tree: Vec<EggvizExplanationTree>,
+}
 
-    pub fn rewrite_auto(&mut self) -> LinkedList<EggvizRewriteRuleLabel> {
-        self.sched_state.rewrite(
-            &mut self.runner,
-            &self.rewrite_rules,
-            NonZeroUsize::new(1).unwrap(),
-            None,
-        )
-    }
+impl EggvizExplanation {
+    fn from_egg<L: EggvizLanguage>(
+        mut explanation: egg::Explanation<L>,
+    ) -> Result<Self, EggvizRuntimeError> {
+        // Egg annotates the rule on the subterm where it was applied. Find it
+        // anywhere in the term:
+        fn flat_term_rule<L: egg::Language>(
+            flat_term: &egg::FlatTerm<L>,
+        ) -> Option<(Option<egg::Symbol>, Option<egg::Symbol>)> {
+            if flat_term.forward_rule.is_some() || flat_term.backward_rule.is_some() {
+                Some((flat_term.forward_rule, flat_term.backward_rule))
+            } else {
+                flat_term.children.iter().find_map(flat_term_rule)
+            }
+        }
 
-    pub fn dump_graph(&self) -> String {
-        // TODO: this should be changed to actually return a usable graph
-        // representation. For now, just print the graph:
-        format!("{:?}", self.runner.egraph.dump())
-    }
+        let tree = explanation
+            .explanation_trees
+            .iter()
+            .map(EggvizExplanationTree::from_egg)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let flat = explanation
+            .make_flat_explanation()
+            .iter()
+            .map(|flat_term| {
+                let rule = flat_term_rule(flat_term)
+                    .map(|(forward_rule, backward_rule)| {
+                        EggvizExplanationRule::from_egg(forward_rule, backward_rule)
+                    })
+                    .transpose()?
+                    .flatten();
 
-    pub fn current_graph(&self) -> HashMap<String, HashMap<u64, (String, Vec<String>)>> {
+                Ok(EggvizExplanationStep {
+                    rule,
+                    term: flat_term.get_recexpr().to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, EggvizRuntimeError>>()?;
+
+        Ok(EggvizExplanation { flat, tree })
+    }
+}
+
+/// Cost function for extracting terms from the e-graph of an
+/// [`EggvizRuntime`].
+///
+/// In contrast to [`egg::CostFunction`], costs are uniformly represented as
+/// [`f64`], such that cost functions can be selected at runtime.
+pub trait EggvizCostFunction<L: EggvizLanguage> {
+    /// Cost of an e-node, given the costs of each of its children (in order).
+    fn cost(&mut self, enode: &L, child_costs: &[f64]) -> f64;
+}
+
+impl<L: EggvizLanguage> EggvizCostFunction<L> for egg::AstSize {
+    fn cost(&mut self, _enode: &L, child_costs: &[f64]) -> f64 {
+        1.0 + child_costs.iter().sum::<f64>()
+    }
+}
+
+impl<L: EggvizLanguage> EggvizCostFunction<L> for egg::AstDepth {
+    fn cost(&mut self, _enode: &L, child_costs: &[f64]) -> f64 {
+        1.0 + child_costs.iter().copied().fold(0.0, f64::max)
+    }
+}
+
+/// Adapter to use an [`EggvizCostFunction`] with an [`egg::Extractor`].
+struct EggvizCostFunctionAdapter<'a, L: EggvizLanguage>(&'a mut dyn EggvizCostFunction<L>);
+
+impl<'a, L: EggvizLanguage> egg::CostFunction<L> for EggvizCostFunctionAdapter<'a, L> {
+    type Cost = f64;
+
+    fn cost<C>(&mut self, enode: &L, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(egg::Id) -> Self::Cost,
+    {
+        let child_costs: Vec<f64> = egg::Language::children(enode)
+            .iter()
+            .map(|id| costs(*id))
+            .collect();
+        self.0.cost(enode, &child_costs)
+    }
+}
+
+/// Term extracted from an e-class of the e-graph.
+#[derive(Clone, Debug)]
+pub struct EggvizExtraction {
+    /// E-class the term was extracted from.
+    pub eclass: egg::Id,
+
+    /// Cost of the term, as determined by the [`EggvizCostFunction`] used.
+    pub cost: f64,
+
+    /// Egg string representation of the term.
+    pub term: String,
+}
+
+#[derive(Clone, Debug)]
+pub enum EggvizRuntimeError {
+    ParseError(EggvizProgramParseError),
+    /// All diagnostics collected while parsing a program and its rewrite
+    /// rules, at least one of which is an error.
+    ParseErrors(Vec<EggvizProgramParseError>),
+    UnknownRewriteRule(EggvizRewriteRuleLabel),
+    TermNotRepresented(String),
+    TermsNotEquivalent(String, String),
+    UnknownEClass(egg::Id),
+    /// The match is not found in the current e-graph (anymore).
+    RuleMatchNotFound(EggvizRuleMatch),
+    /// The condition of a conditional rewrite rule does not hold for the match.
+    RuleConditionFailed(EggvizRuleMatch),
+    /// Replaying a recorded [`EggvizSession`] did not reproduce it.
+    SessionMismatch(String),
+    InternalError(String),
+}
+
+impl Display for EggvizRuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            EggvizRuntimeError::ParseError(e) => format!("Parsing Error: {}", e).fmt(f),
+            EggvizRuntimeError::ParseErrors(diagnostics) => {
+                write!(f, "Parsing Error:")?;
+                for e in diagnostics {
+                    write!(f, "\n{}: {}", e.severity, e)?;
+                }
+                Ok(())
+            }
+            EggvizRuntimeError::UnknownRewriteRule(l) => {
+                format!("Error: Rewrite rule `{}` does not exist.", l).fmt(f)
+            }
+            EggvizRuntimeError::TermNotRepresented(t) => {
+                format!("Error: Term `{}` is not represented in the e-graph.", t).fmt(f)
+            }
+            EggvizRuntimeError::TermsNotEquivalent(l, r) => {
+                format!("Error: Terms `{}` and `{}` are not equivalent.", l, r).fmt(f)
+            }
+            EggvizRuntimeError::UnknownEClass(id) => {
+                format!("Error: E-class {} does not exist.", id).fmt(f)
+            }
+            EggvizRuntimeError::RuleMatchNotFound(m) => format!(
+                "Error: Rewrite rule `{}` does not match e-class {} with this substitution.",
+                m.rule, m.eclass
+            )
+            .fmt(f),
+            EggvizRuntimeError::RuleConditionFailed(m) => format!(
+                "Error: The condition of rewrite rule `{}` does not hold for e-class {}.",
+                m.rule, m.eclass
+            )
+            .fmt(f),
+            EggvizRuntimeError::SessionMismatch(e) => {
+                format!("Error: Unable to restore session: {}", e).fmt(f)
+            }
+            EggvizRuntimeError::InternalError(e) => format!("Internal Error: {}", e).fmt(f),
+        }
+    }
+}
+
+/// E-node added to the e-graph as part of a step.
+#[derive(Clone, Debug)]
+pub struct EggvizStepReportNode {
+    /// E-class the e-node is part of after the step.
+    pub eclass: egg::Id,
+
+    /// Function or constant name of the e-node.
+    pub function: String,
+
+    /// (Canonical) child e-classes of the e-node.
+    pub children: Vec<egg::Id>,
+}
+
+/// Structured report of the changes made to the e-graph by a single step of an
+/// [`EggvizRuntime`].
+#[derive(Clone, Debug)]
+pub struct EggvizStepReport {
+    /// Rewrite rules which changed the e-graph in this step.
+    pub applied_rules: LinkedList<EggvizRewriteRuleLabel>,
+
+    /// E-nodes not represented in the e-graph prior to this step.
+    pub added_nodes: Vec<EggvizStepReportNode>,
+
+    /// E-classes created in this step.
+    pub added_classes: Vec<egg::Id>,
+
+    /// E-classes merged into another e-class in this step, as pairs of the
+    /// previous e-class id and its new canonical id.
+    pub merged_classes: Vec<(egg::Id, egg::Id)>,
+
+    /// Matches of all rewrite rules applied in this step, regardless of
+    /// whether they changed the e-graph.
+    pub matches: Vec<EggvizRuleMatch>,
+}
+
+impl EggvizStepReport {
+    fn new<L: EggvizLanguage, N: egg::Analysis<L>>(
+        prev_egraph: &egg::EGraph<L, N>,
+        egraph: &egg::EGraph<L, N>,
+        applied_rules: LinkedList<EggvizRewriteRuleLabel>,
+        matches: Vec<EggvizRuleMatch>,
+    ) -> Self {
+        // Canonicalize all previously existing e-nodes with respect to the new
+        // e-graph, such that they can be compared to the new e-nodes:
+        let prev_nodes: HashSet<L> = prev_egraph
+            .classes()
+            .flat_map(|eclass| eclass.nodes.iter())
+            .map(|enode| enode.clone().map_children(|id| egraph.find(id)))
+            .collect();
+
+        let mut added_nodes: Vec<EggvizStepReportNode> = egraph
+            .classes()
+            .flat_map(|eclass| {
+                eclass
+                    .nodes
+                    .iter()
+                    .filter(|enode| !prev_nodes.contains(enode))
+                    .map(move |enode| EggvizStepReportNode {
+                        eclass: eclass.id,
+                        function: enode.get_function_name().to_string(),
+                        children: egg::Language::children(enode).to_vec(),
+                    })
+            })
+            .collect();
+        added_nodes.sort_by_key(|node| node.eclass);
+
+        let prev_classes: HashSet<egg::Id> =
+            prev_egraph.classes().map(|eclass| eclass.id).collect();
+        let mut added_classes: Vec<egg::Id> = egraph
+            .classes()
+            .map(|eclass| eclass.id)
+            .filter(|id| !prev_classes.contains(id))
+            .collect();
+        added_classes.sort();
+
+        let mut merged_classes: Vec<(egg::Id, egg::Id)> = prev_egraph
+            .classes()
+            .map(|prev_eclass| (prev_eclass.id, egraph.find(prev_eclass.id)))
+            .filter(|(prev_id, id)| prev_id != id)
+            .collect();
+        merged_classes.sort();
+
+        EggvizStepReport {
+            applied_rules,
+            added_nodes,
+            added_classes,
+            merged_classes,
+            matches,
+        }
+    }
+}
+
+/// Statistics of a single iteration of the [`egg::Runner`].
+#[derive(Clone, Debug)]
+pub struct EggvizIterationStats {
+    pub egraph_nodes: usize,
+    pub egraph_classes: usize,
+
+    /// Number of e-classes changed per rewrite rule.
+    pub applied: Vec<(EggvizRewriteRuleLabel, usize)>,
+
+    /// Time spent searching, applying, rebuilding and in total, in seconds.
+    pub search_time: f64,
+    pub apply_time: f64,
+    pub rebuild_time: f64,
+    pub total_time: f64,
+}
+
+impl EggvizIterationStats {
+    fn from_egg(iteration: &egg::Iteration<()>) -> Self {
+        EggvizIterationStats {
+            egraph_nodes: iteration.egraph_nodes,
+            egraph_classes: iteration.egraph_classes,
+            applied: iteration
+                .applied
+                .iter()
+                .map(|(rule, count)| {
+                    (
+                        EggvizRewriteRuleLabel::from_str(rule.as_str()).unwrap(),
+                        *count,
+                    )
+                })
+                .collect(),
+            search_time: iteration.search_time,
+            apply_time: iteration.apply_time,
+            rebuild_time: iteration.rebuild_time,
+            total_time: iteration.total_time,
+        }
+    }
+}
+
+/// Report of running the rewrite rules until saturation or any of the
+/// [`EggvizRunLimits`] is reached.
+#[derive(Clone, Debug)]
+pub struct EggvizSaturationReport {
+    /// Reason egg stopped applying rewrite rules.
+    pub stop_reason: egg::StopReason,
+
+    /// Statistics of each iteration run.
+    pub iterations: Vec<EggvizIterationStats>,
+
+    /// Changes made to the e-graph over all iterations.
+    pub step: EggvizStepReport,
+}
+
+/// Graphviz DOT representation of an e-graph, see
+/// [`EggvizRuntime::dump_graph`].
+struct EggvizDot<'a, L: EggvizLanguage, N: egg::Analysis<L>> {
+    egraph: &'a egg::EGraph<L, N>,
+    data_to_string: fn(&N::Data) -> String,
+    /// E-nodes to highlight, given by their e-class, function name and
+    /// children.
+    highlighted_nodes: HashSet<(egg::Id, String, Vec<egg::Id>)>,
+    highlighted_classes: HashSet<egg::Id>,
+}
+
+impl<'a, L: EggvizLanguage, N: egg::Analysis<L>> EggvizDot<'a, L, N> {
+    /// Escape a string for use in a record label, where braces, angle
+    /// brackets and vertical bars have a special meaning.
+    fn escape(s: &str) -> String {
+        let mut escaped = String::with_capacity(s.len());
+        for c in s.chars() {
+            if "{}<>|\"\\ ".contains(c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+}
+
+impl<'a, L: EggvizLanguage, N: egg::Analysis<L>> Display for EggvizDot<'a, L, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        const HIGHLIGHT_COLOR: &str = "orange";
+
+        writeln!(f, "digraph egraph {{")?;
+        writeln!(f, "  compound=true")?;
+        writeln!(f, "  clusterrank=local")?;
+
+        let mut eclasses: Vec<_> = self.egraph.classes().collect();
+        eclasses.sort_by_key(|eclass| eclass.id);
+
+        for eclass in eclasses.iter() {
+            writeln!(f, "  subgraph cluster_{} {{", eclass.id)?;
+            let data = (self.data_to_string)(&eclass.data);
+            writeln!(
+                f,
+                "    label=\"C{}{}{}\"",
+                eclass.id,
+                if data.is_empty() { "" } else { " " },
+                data.replace('\\', "\\\\").replace('"', "\\\""),
+            )?;
+            if self.highlighted_classes.contains(&eclass.id) {
+                writeln!(f, "    style=\"dashed,bold\" color={}", HIGHLIGHT_COLOR)?;
+            } else {
+                writeln!(f, "    style=dashed")?;
+            }
+
+            for (idx, enode) in eclass.nodes.iter().enumerate() {
+                let function = enode.get_function_name();
+                let children: Vec<egg::Id> = egg::Language::children(enode)
+                    .iter()
+                    .map(|child| self.egraph.find(*child))
+                    .collect();
+
+                // Records are laid out horizontally, so the function name is
+                // put on top of a row of ports for the children:
+                let label = if children.is_empty() {
+                    Self::escape(function)
+                } else {
+                    let ports: Vec<String> = (0..children.len())
+                        .map(|i| format!("<c{}> {}", i, i))
+                        .collect();
+                    format!("{{{}|{{{}}}}}", Self::escape(function), ports.join("|"))
+                };
+                let highlighted =
+                    self.highlighted_nodes
+                        .contains(&(eclass.id, function.to_string(), children));
+                writeln!(
+                    f,
+                    "    \"{}.{}\" [shape=record label=\"{}\"{}]",
+                    eclass.id,
+                    idx,
+                    label,
+                    if highlighted {
+                        format!(" style=filled fillcolor={}", HIGHLIGHT_COLOR)
+                    } else {
+                        String::new()
+                    },
+                )?;
+            }
+            writeln!(f, "  }}")?;
+        }
+
+        // Edges must point at a node within the child's cluster, and are then
+        // clipped at the cluster's boundary. Graphviz does not support
+        // clipping edges at the cluster they originate from:
+        for eclass in eclasses.iter() {
+            for (idx, enode) in eclass.nodes.iter().enumerate() {
+                for (i, child) in egg::Language::children(enode).iter().enumerate() {
+                    let child = self.egraph.find(*child);
+                    write!(f, "  \"{}.{}\":c{} -> \"{}.0\"", eclass.id, idx, i, child)?;
+                    if child == eclass.id {
+                        writeln!(f)?;
+                    } else {
+                        writeln!(f, " [lhead=cluster_{}]", child)?;
+                    }
+                }
+            }
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+/// Result of querying whether two terms are equivalent in the current e-graph.
+#[derive(Clone, Debug)]
+pub struct EggvizEquivalence {
+    /// E-class of the left term, if it is represented in the e-graph.
+    pub lhs: Option<egg::Id>,
+
+    /// E-class of the right term, if it is represented in the e-graph.
+    pub rhs: Option<egg::Id>,
+}
+
+impl EggvizEquivalence {
+    /// Both terms are represented in the e-graph, in the same e-class.
+    pub fn equivalent(&self) -> bool {
+        self.lhs.is_some() && self.lhs == self.rhs
+    }
+}
+
+/// Operation performed as a single step on an [`EggvizRuntime`], recorded
+/// such that it can be replayed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EggvizSessionStep {
+    /// See [`EggvizRuntime::rewrite_rule`].
+    RewriteRule(EggvizRewriteRuleLabel),
+    /// See [`EggvizRuntime::rewrite_rule_match`].
+    RewriteRuleMatch(EggvizRuleMatch),
+    /// See [`EggvizRuntime::rewrite_auto`].
+    RewriteAuto,
+    /// See [`EggvizRuntime::run_until_saturated`]. Recorded with the number
+    /// of iterations actually run instead of a time limit, such that replaying
+    /// the step is deterministic.
+    RunUntilSaturated {
+        iterations: NonZeroUsize,
+        node_limit: usize,
+    },
+    /// See [`EggvizRuntime::union`].
+    Union(egg::Id, egg::Id),
+    /// See [`EggvizRuntime::add_term`].
+    AddTerm(String),
+}
+
+/// Recorded session of an [`EggvizRuntime`], obtained through
+/// [`EggvizRuntime::session`] and restored through
+/// [`EggvizRuntime::from_session`].
+///
+/// A session holds the program and rewrite rules the runtime was constructed
+/// from, and the steps which led to its current e-graph. Steps which were
+/// undone are not part of the session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EggvizSession {
+    pub program: String,
+
+    /// Rewrite rules, as passed to [`EggvizRuntime::new`].
+    pub rewrite_rules: Vec<(Option<String>, String, EggvizRewriteDirection, String)>,
+
+    /// Steps in the history of the runtime, in order, along with the rewrite
+    /// rules each of them applied.
+    pub steps: Vec<(EggvizSessionStep, LinkedList<EggvizRewriteRuleLabel>)>,
+
+    /// Size of the resulting e-graph, used to check that replaying the
+    /// session reproduced it.
+    pub egraph_nodes: usize,
+    pub egraph_classes: usize,
+}
+
+/// Identifiers of the e-nodes in an e-graph, which remain stable as the
+/// e-graph changes.
+///
+/// E-nodes are identified by their canonical form, which changes as e-classes
+/// are merged. Hence, the identifiers must be updated after every change to
+/// the e-graph. New e-nodes are assigned identifiers in the order of their
+/// e-classes, such that replaying the same steps yields the same identifiers.
+#[derive(Clone)]
+struct EggvizENodeIds<L: egg::Language> {
+    ids: HashMap<L, u64>,
+    next_id: u64,
+}
+
+impl<L: egg::Language> EggvizENodeIds<L> {
+    fn new() -> Self {
+        EggvizENodeIds {
+            ids: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Canonicalize the known e-nodes with respect to the (rebuilt) e-graph,
+    /// and assign identifiers to all e-nodes not known yet.
+    fn update<N: egg::Analysis<L>>(&mut self, egraph: &egg::EGraph<L, N>) {
+        let mut ids: HashMap<L, u64> = HashMap::with_capacity(self.ids.len());
+        for (enode, id) in self.ids.drain() {
+            // E-nodes which became congruent keep the oldest identifier:
+            let enode = enode.map_children(|child| egraph.find(child));
+            let entry = ids.entry(enode).or_insert(id);
+            *entry = (*entry).min(id);
+        }
+
+        let mut eclasses: Vec<_> = egraph.classes().collect();
+        eclasses.sort_by_key(|eclass| eclass.id);
+        for enode in eclasses.iter().flat_map(|eclass| eclass.nodes.iter()) {
+            if !ids.contains_key(enode) {
+                ids.insert(enode.clone(), self.next_id);
+                self.next_id += 1;
+            }
+        }
+
+        self.ids = ids;
+    }
+
+    fn get(&self, enode: &L) -> Option<u64> {
+        self.ids.get(enode).copied()
+    }
+}
+
+/// Entry in the undo or redo history of an [`EggvizRuntime`].
+///
+/// Holds the e-graph, e-node identifiers and parse state on the other side of
+/// a step, along with the rewrite rules which were applied as part of this
+/// step.
+struct EggvizRuntimeHistoryEntry<P: EggvizProgram> {
+    egraph: egg::EGraph<P::Language, P::Analysis>,
+    enode_ids: EggvizENodeIds<P::Language>,
+    parse_state: P::ParseState,
+    applied_rules: LinkedList<EggvizRewriteRuleLabel>,
+    step: EggvizSessionStep,
+}
+
+pub struct EggvizRuntime<P: EggvizProgram> {
+    program: P,
+    /// Sources of the program and rewrite rules, retained to record sessions.
+    program_str: String,
+    rewrite_rules_str: Vec<(Option<String>, String, EggvizRewriteDirection, String)>,
+    parse_state: P::ParseState,
+    rewrite_rules: Vec<egg::Rewrite<P::Language, P::Analysis>>,
+    rewrite_conditions: HashMap<EggvizRewriteRuleLabel, EggvizCondition<P::Language>>,
+    sched_state: EggvizSingleStepSchedulerState,
+    runner: egg::Runner<P::Language, P::Analysis>,
+    enode_ids: EggvizENodeIds<P::Language>,
+    undo_history: Vec<EggvizRuntimeHistoryEntry<P>>,
+    redo_history: Vec<EggvizRuntimeHistoryEntry<P>>,
+    diagnostics: Vec<EggvizProgramParseError>,
+    /// Names of the program's root terms, corresponding to `runner.roots`.
+    root_names: Vec<String>,
+}
+
+// The runtime snapshots the e-graph for its history, which requires the
+// analysis data to be cloneable:
+impl<P: EggvizProgram> EggvizRuntime<P>
+where
+    <P::Analysis as egg::Analysis<P::Language>>::Data: Clone,
+{
+    pub fn new(
+        program_str: impl AsRef<str>,
+        rewrite_rules_str: impl IntoIterator<
+            Item = (
+                Option<impl AsRef<str>>,
+                impl AsRef<str>,
+                EggvizRewriteDirection,
+                impl AsRef<str>,
+            ),
+        >,
+    ) -> Result<Self, EggvizRuntimeError> {
+        // Try to construct a program from the given string, collecting all
+        // diagnostics. Further ensure that the context doesn't lie about the
+        // parsing error type.
+        let mut diagnostics: Vec<EggvizProgramParseError> = Vec::new();
+        let mut parse_state = P::ParseState::default();
+        let program = P::parse_str(program_str.as_ref(), &mut parse_state, &mut diagnostics);
+        if let Some(e) = diagnostics.iter().find(|e| {
+            matches!(
+                e.context,
+                Some(EggvizProgramParseContext::RewriteRule { .. })
+            )
+        }) {
+            return Err(EggvizRuntimeError::InternalError(format!(
+                "Invalid parse error context for parsing program: {:?}",
+                e
+            )));
+        }
+
+        // Now try to parse the passed rewrite rules in the program's context.
+        // We do this even if the program failed to parse, checking the rules
+        // against as much of it as could be parsed, such that all diagnostics
+        // are reported at once. This also performs a sanity check that the
+        // assigned labels are unique. We require this for our single-step
+        // scheduler:
+        let mut rewrite_rule_labels: HashSet<EggvizRewriteRuleLabel> = HashSet::new();
+        // Conditions of conditional rewrite rules are kept alongside the rules,
+        // to check them when applying individual matches:
+        let mut rewrite_conditions = HashMap::new();
+        let mut rewrite_rules = Vec::new();
+        let mut rewrite_rule_sources = Vec::new();
+        for (idx, (opt_str_label, left, direction, right)) in
+            rewrite_rules_str.into_iter().enumerate()
+        {
+            rewrite_rule_sources.push((
+                opt_str_label
+                    .as_ref()
+                    .map(|label| label.as_ref().to_string()),
+                left.as_ref().to_string(),
+                direction,
+                right.as_ref().to_string(),
+            ));
+
+            let rwr_label = if let Some(str_label) = opt_str_label {
+                EggvizRewriteRuleLabel::Supplied(str_label.as_ref().to_string())
+            } else {
+                EggvizRewriteRuleLabel::Indexed(idx)
+            };
+
+            let mut labels = vec![rwr_label.clone()];
+            if direction == EggvizRewriteDirection::Bidirectional {
+                labels.push(rwr_label.reversed());
+            }
+            if let Some(duplicate) = labels
+                .into_iter()
+                .find(|label| !rewrite_rule_labels.insert(label.clone()))
+            {
+                // Report the duplicate along with all other diagnostics, but
+                // don't try to build the rule:
+                diagnostics.push(EggvizProgramParseError::context_less_owned(format!(
+                    "Rewrite rule {} was duplicated.",
+                    duplicate
+                )));
+                continue;
+            }
+
+            let rule_diagnostics = diagnostics.len();
+            let rewrite_rule = P::parse_rewrite_rule(
+                &mut parse_state,
+                &rwr_label,
+                left.as_ref(),
+                right.as_ref(),
+                &mut diagnostics,
+            );
+            if let Some(e) = diagnostics[rule_diagnostics..].iter().find(|e| {
+                matches!(
+                    e.context,
+                    Some(EggvizProgramParseContext::RewriteRule { ref label, .. })
+                        if *label != rwr_label
+                )
+            }) {
+                return Err(EggvizRuntimeError::InternalError(format!(
+                    "Invalid parse error context for parsing rewrite rule {:?}: {:?}",
+                    &rwr_label, e
+                )));
+            }
+            let rewrite_rule = match rewrite_rule {
+                Some(rewrite_rule) => rewrite_rule,
+                None if diagnostics[rule_diagnostics..].iter().any(|e| e.is_error()) => continue,
+                None => {
+                    return Err(EggvizRuntimeError::InternalError(format!(
+                        "Parsing rewrite rule {:?} failed without reporting an error",
+                        &rwr_label
+                    )))
+                }
+            };
+
+            let left = rewrite_rule.left_to_egg();
+            let right = rewrite_rule.right_to_egg();
+            let condition = rewrite_rule.condition_to_egg();
+            if left == right && condition.is_none() {
+                diagnostics.push(
+                    EggvizProgramParseError::context_less_owned(format!(
+                        "Rewrite rule {} has identical sides and thus no effect.",
+                        rwr_label
+                    ))
+                    .into_warning(),
+                );
+            }
+
+            let mut rewrites = vec![(rwr_label.clone(), &left, &right)];
+            if direction == EggvizRewriteDirection::Bidirectional {
+                rewrites.push((rwr_label.reversed(), &right, &left));
+            }
+            for (label, from, to) in rewrites {
+                match Self::build_rewrite(
+                    label,
+                    from,
+                    to,
+                    condition.as_ref(),
+                    &mut rewrite_conditions,
+                ) {
+                    Ok(rewrite) => rewrite_rules.push(rewrite),
+                    Err(EggvizRuntimeError::ParseError(e)) => diagnostics.push(e),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        if diagnostics.iter().any(|e| e.is_error()) {
+            return Err(EggvizRuntimeError::ParseErrors(diagnostics));
+        }
+        let program = program.ok_or_else(|| {
+            EggvizRuntimeError::InternalError(
+                "Parsing the program failed without reporting an error".to_string(),
+            )
+        })?;
+
+        // Now, convert the program's root terms into egg expressions, annotated
+        // with the type of the language we're using:
+        let (root_names, exprs): (Vec<String>, Vec<egg::RecExpr<P::Language>>) = program
+            .roots_to_egg()
+            .into_iter()
+            .map(|(name, expr)| {
+                let expr = expr.parse().map_err(|e| {
+                    EggvizRuntimeError::InternalError(format!(
+                        "Egg reported error while trying to parse the generated program \
+                         expression for root term {:?}: {:?}",
+                        name, e
+                    ))
+                })?;
+                Ok((name, expr))
+            })
+            .collect::<Result<Vec<_>, EggvizRuntimeError>>()?
+            .into_iter()
+            .unzip();
+        if exprs.is_empty() {
+            return Err(EggvizRuntimeError::InternalError(
+                "Program does not have any root terms".to_string(),
+            ));
+        }
+
+        // Finally, we create an instance of our single-step scheduler. This
+        // scheduler allows our runtime to retain control over egg's behavior
+        // while we kick off control to egg's Runner. We share state with this
+        // scheduler to provide instructions and collect responses.
+        let sched_state = EggvizSingleStepScheduler::initial_state();
+
+        // Piece it all together in an instance of egg's Runner (the scheduler
+        // is set later implicitly by `rewrite_rule()`:
+        let mut runner = exprs
+            .iter()
+            .fold(
+                egg::Runner::new(program.analysis(&parse_state))
+                    // required to explain equivalences, must be enabled prior to
+                    // adding any expressions
+                    .with_explanations_enabled(),
+                |runner, expr| runner.with_expr(expr),
+            )
+            // required, the default timeout is 5sec which would make an
+            // instance of EggvizRuntime unusable after that
+            .with_time_limit(std::time::Duration::MAX);
+
+        // The analysis may have merged e-classes while adding the root terms,
+        // restore the e-graph's invariants before anyone looks at it:
+        runner.egraph.rebuild();
+        let mut enode_ids = EggvizENodeIds::new();
+        enode_ids.update(&runner.egraph);
+
+        Ok(EggvizRuntime {
+            program,
+            program_str: program_str.as_ref().to_string(),
+            rewrite_rules_str: rewrite_rule_sources,
+            parse_state,
+            rewrite_rules,
+            rewrite_conditions,
+            sched_state,
+            runner,
+            enode_ids,
+            undo_history: Vec::new(),
+            redo_history: Vec::new(),
+            diagnostics,
+            root_names,
+        })
+    }
+
+    /// Names of the program's root terms along with their (canonical)
+    /// e-classes, in program order.
+    pub fn roots(&self) -> Vec<(String, egg::Id)> {
+        self.root_names
+            .iter()
+            .cloned()
+            .zip(
+                self.runner
+                    .roots
+                    .iter()
+                    .map(|root| self.runner.egraph.find(*root)),
+            )
+            .collect()
+    }
+
+    /// (Canonical) e-class of the root term with the given name.
+    pub fn root(&self, name: &str) -> Option<egg::Id> {
+        self.root_names
+            .iter()
+            .position(|root_name| root_name == name)
+            .map(|idx| self.runner.egraph.find(self.runner.roots[idx]))
+    }
+
+    /// Warnings reported while parsing the program and its rewrite rules.
+    pub fn diagnostics(&self) -> &[EggvizProgramParseError] {
+        &self.diagnostics
+    }
+
+    /// Construct an egg rewrite from the string-representations of a rewrite
+    /// rule's sides and optional side condition.
+    fn build_rewrite(
+        rwr_label: EggvizRewriteRuleLabel,
+        left: &str,
+        right: &str,
+        condition: Option<&EggvizRewriteCondition>,
+        rewrite_conditions: &mut HashMap<EggvizRewriteRuleLabel, EggvizCondition<P::Language>>,
+    ) -> Result<egg::Rewrite<P::Language, P::Analysis>, EggvizRuntimeError> {
+        let left: egg::Pattern<P::Language> = egg::Pattern::from_str(left).map_err(|e| {
+            EggvizRuntimeError::InternalError(format!(
+                "Egg reported an error while trying to parse the generated \
+                 left-hand rewrite rule expression for rule {:?}: {:?}",
+                rwr_label, e
+            ))
+        })?;
+        let right: egg::Pattern<P::Language> = egg::Pattern::from_str(right).map_err(|e| {
+            EggvizRuntimeError::InternalError(format!(
+                "Egg reported an error while trying to parse the generated \
+                 right-hand rewrite rule expression for rule {:?}: {:?}",
+                rwr_label, e
+            ))
+        })?;
+        let condition = condition
+            .map(EggvizCondition::from_egg)
+            .transpose()
+            .map_err(|e| {
+                EggvizRuntimeError::InternalError(format!("{} (in rule {:?})", e, rwr_label))
+            })?;
+
+        // Generic variables on the right-hand side or in the condition must be
+        // bound by the left-hand side. This is a user error (which may only
+        // surface in the reverse direction of a bidirectional rule), so don't
+        // leave it to egg to report:
+        let bound = left.vars();
+        let mut used = right.vars();
+        if let Some(ref condition) = condition {
+            used.extend(egg::Condition::<P::Language, P::Analysis>::vars(condition));
+        }
+        if let Some(unbound) = used.into_iter().find(|var| !bound.contains(var)) {
+            return Err(EggvizRuntimeError::ParseError(
+                EggvizProgramParseError::context_less_owned(format!(
+                    "Rewrite rule {} uses generic variable '{}', which is not bound by \
+                     the left-hand side it is applied from.",
+                    rwr_label,
+                    unbound.to_string().trim_start_matches('?')
+                )),
+            ));
+        }
+
+        match condition {
+            None => egg::Rewrite::new(rwr_label.to_string(), left, right),
+            Some(condition) => {
+                rewrite_conditions.insert(rwr_label.clone(), condition.clone());
+                egg::Rewrite::new(
+                    rwr_label.to_string(),
+                    left,
+                    egg::ConditionalApplier {
+                        condition,
+                        applier: right,
+                    },
+                )
+            }
+        }
+        .map_err(|e| {
+            EggvizRuntimeError::InternalError(format!(
+                "Egg reported an error while constructing a rewrite at {:?}: {:?}",
+                rwr_label, e,
+            ))
+        })
+    }
+
+    /// Parse a term in the context of the program, without retaining any
+    /// changes it makes to the program's parse state.
+    fn parse_term(&self, term_str: &str) -> Result<egg::RecExpr<P::Language>, EggvizRuntimeError> {
+        let mut parse_state = self.parse_state.clone();
+        self.parse_term_with(&mut parse_state, term_str)
+    }
+
+    /// Parse a term in the context of the program, recording any changes it
+    /// makes to the passed parse state.
+    fn parse_term_with(
+        &self,
+        parse_state: &mut P::ParseState,
+        term_str: &str,
+    ) -> Result<egg::RecExpr<P::Language>, EggvizRuntimeError> {
+        self.program
+            .parse_term(parse_state, term_str)
+            .map_err(EggvizRuntimeError::ParseError)?
+            .parse()
+            .map_err(|e| {
+                EggvizRuntimeError::InternalError(format!(
+                    "Egg reported error while trying to parse the generated term expression: {:?}",
+                    e
+                ))
+            })
+    }
+
+    /// Record a step which transformed `prev_egraph` and `prev_parse_state`
+    /// into the current e-graph and parse state, and update the e-node
+    /// identifiers accordingly.
+    fn record_step(
+        &mut self,
+        prev_egraph: egg::EGraph<P::Language, P::Analysis>,
+        prev_parse_state: P::ParseState,
+        applied_rules: LinkedList<EggvizRewriteRuleLabel>,
+        step: EggvizSessionStep,
+    ) {
+        let mut enode_ids = self.enode_ids.clone();
+        enode_ids.update(&self.runner.egraph);
+        self.undo_history.push(EggvizRuntimeHistoryEntry {
+            egraph: prev_egraph,
+            enode_ids: std::mem::replace(&mut self.enode_ids, enode_ids),
+            parse_state: prev_parse_state,
+            applied_rules,
+            step,
+        });
+
+        // A new step invalidates any steps previously undone:
+        self.redo_history.clear();
+    }
+
+    /// Perform a single step through the scheduler, recording it in the
+    /// history and reporting the changes it made to the e-graph.
+    fn step(
+        &mut self,
+        step: EggvizSessionStep,
+        step_fn: impl FnOnce(
+            &EggvizSingleStepSchedulerState,
+            &mut egg::Runner<P::Language, P::Analysis>,
+            &[egg::Rewrite<P::Language, P::Analysis>],
+        ) -> (LinkedList<EggvizRewriteRuleLabel>, Vec<EggvizRuleMatch>),
+    ) -> EggvizStepReport {
+        let prev_egraph = self.runner.egraph.clone();
+        let (applied_rules, matches) =
+            step_fn(&self.sched_state, &mut self.runner, &self.rewrite_rules);
+
+        let report =
+            EggvizStepReport::new(&prev_egraph, &self.runner.egraph, applied_rules, matches);
+        // Steps which did not apply any rewrite rules are not recorded:
+        if !report.applied_rules.is_empty() {
+            self.record_step(
+                prev_egraph,
+                self.parse_state.clone(),
+                report.applied_rules.clone(),
+                step,
+            );
+        }
+
+        report
+    }
+
+    pub fn rewrite_rule(&mut self, rule: EggvizRewriteRuleLabel) -> EggvizStepReport {
+        self.step(
+            EggvizSessionStep::RewriteRule(rule.clone()),
+            |sched_state, runner, rewrite_rules| {
+                let (applied, matches) =
+                    sched_state.rewrite_rule(runner, rewrite_rules.iter(), rule.clone());
+                if applied {
+                    (LinkedList::from([rule]), matches)
+                } else {
+                    (LinkedList::new(), matches)
+                }
+            },
+        )
+    }
+
+    /// Manually merge two e-classes and restore congruence, recorded as a step
+    /// applying the [`EggvizRewriteRuleLabel::Union`] pseudo-rule.
+    pub fn union(
+        &mut self,
+        a: egg::Id,
+        b: egg::Id,
+    ) -> Result<EggvizStepReport, EggvizRuntimeError> {
+        for id in [a, b] {
+            if !self.runner.egraph.classes().any(|eclass| eclass.id == id) {
+                return Err(EggvizRuntimeError::UnknownEClass(id));
+            }
+        }
+
+        Ok(self.step(EggvizSessionStep::Union(a, b), |_, runner, _| {
+            // Unions need a justification when explanations are enabled:
+            let label = EggvizRewriteRuleLabel::Union;
+            if runner.egraph.union_trusted(a, b, label.to_string()) {
+                runner.egraph.rebuild();
+                (LinkedList::from([label]), Vec::new())
+            } else {
+                (LinkedList::new(), Vec::new())
+            }
+        }))
+    }
+
+    /// Apply a rewrite rule only at a single one of its matches.
+    ///
+    /// Fails if the match is stale, i.e. not among the rule's matches in the
+    /// current e-graph, or if the rule's condition does not hold for it.
+    pub fn rewrite_rule_match(
+        &mut self,
+        rule_match: EggvizRuleMatch,
+    ) -> Result<EggvizStepReport, EggvizRuntimeError> {
+        let rewrite = self.find_rewrite(&rule_match.rule)?;
+        let selected = rule_match.select(rewrite, rewrite.search(&self.runner.egraph));
+        let subst = match selected.first() {
+            Some(search_match) => &search_match.substs[0],
+            None => return Err(EggvizRuntimeError::RuleMatchNotFound(rule_match)),
+        };
+        if let Some(condition) = self.rewrite_conditions.get(&rule_match.rule) {
+            if !condition.holds(&self.runner.egraph, subst) {
+                return Err(EggvizRuntimeError::RuleConditionFailed(rule_match));
+            }
+        }
+
+        let step = EggvizSessionStep::RewriteRuleMatch(rule_match.clone());
+        Ok(self.step(step, |sched_state, runner, rewrite_rules| {
+            let rule = rule_match.rule.clone();
+            let (applied, matches) =
+                sched_state.rewrite_rule_match(runner, rewrite_rules.iter(), rule_match);
+            if applied {
+                (LinkedList::from([rule]), matches)
+            } else {
+                (LinkedList::new(), matches)
+            }
+        }))
+    }
+
+    pub fn rewrite_auto(&mut self) -> EggvizStepReport {
+        self.step(
+            EggvizSessionStep::RewriteAuto,
+            |sched_state, runner, rewrite_rules| {
+                sched_state.rewrite(
+                    runner,
+                    rewrite_rules,
+                    &EggvizRunLimits::iterations(NonZeroUsize::new(1).unwrap()),
+                    None,
+                )
+            },
+        )
+    }
+
+    /// Apply all rewrite rules until the e-graph is saturated, or any of the
+    /// passed limits is reached. This is recorded as a single step in the
+    /// history.
+    pub fn run_until_saturated(&mut self, limits: &EggvizRunLimits) -> EggvizSaturationReport {
+        let first_iteration = self.runner.iterations.len();
+        let step = self.step(
+            EggvizSessionStep::RunUntilSaturated {
+                iterations: limits.iter_limit,
+                node_limit: limits.node_limit,
+            },
+            |sched_state, runner, rewrite_rules| {
+                sched_state.rewrite(runner, rewrite_rules, limits, None)
+            },
+        );
+
+        // The number of iterations run is only known now. Record it in place
+        // of the iteration limit, unless the step was not recorded at all. A
+        // final iteration cut short by a limit before applying any rules must
+        // not be replayed as a regular iteration:
+        let mut iterations = &self.runner.iterations[first_iteration..];
+        if let Some((last, rest)) = iterations.split_last() {
+            if !matches!(last.stop_reason, None | Some(egg::StopReason::Saturated))
+                && last.applied.is_empty()
+            {
+                iterations = rest;
+            }
+        }
+        if !step.applied_rules.is_empty() {
+            if let (Some(entry), Some(iterations)) = (
+                self.undo_history.last_mut(),
+                NonZeroUsize::new(iterations.len()),
+            ) {
+                entry.step = EggvizSessionStep::RunUntilSaturated {
+                    iterations,
+                    node_limit: limits.node_limit,
+                };
+            }
+        }
+
+        EggvizSaturationReport {
+            stop_reason: self
+                .runner
+                .stop_reason
+                .clone()
+                .expect("Egg runner stopped without a stop reason"),
+            iterations: self.runner.iterations[first_iteration..]
+                .iter()
+                .map(EggvizIterationStats::from_egg)
+                .collect(),
+            step,
+        }
+    }
+
+    fn find_rewrite(
+        &self,
+        rule: &EggvizRewriteRuleLabel,
+    ) -> Result<&egg::Rewrite<P::Language, P::Analysis>, EggvizRuntimeError> {
+        let rule_name = rule.to_string();
+        self.rewrite_rules
+            .iter()
+            .find(|rewrite| rewrite.name.as_str() == rule_name)
+            .ok_or_else(|| EggvizRuntimeError::UnknownRewriteRule(rule.clone()))
+    }
+
+    /// Enumerate all matches of a rewrite rule in the current e-graph, without
+    /// applying the rule.
+    ///
+    /// This only searches the e-graph for the rule's left-hand side and leaves
+    /// the e-graph untouched: the rule is neither applied, nor is the e-graph
+    /// rebuilt.
+    pub fn preview_rule(
+        &self,
+        rule: &EggvizRewriteRuleLabel,
+    ) -> Result<Vec<EggvizRuleMatch>, EggvizRuntimeError> {
+        let rewrite = self.find_rewrite(rule)?;
+        Ok(EggvizRuleMatch::from_search_matches(
+            rule,
+            rewrite,
+            &rewrite.search(&self.runner.egraph),
+        ))
+    }
+
+    /// Revert the most recent step, returning whether there was a step to
+    /// undo.
+    pub fn undo(&mut self) -> bool {
+        if let Some(entry) = self.undo_history.pop() {
+            let egraph = std::mem::replace(&mut self.runner.egraph, entry.egraph);
+            let enode_ids = std::mem::replace(&mut self.enode_ids, entry.enode_ids);
+            let parse_state = std::mem::replace(&mut self.parse_state, entry.parse_state);
+            self.redo_history.push(EggvizRuntimeHistoryEntry {
+                egraph,
+                enode_ids,
+                parse_state,
+                applied_rules: entry.applied_rules,
+                step: entry.step,
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Re-apply the most recently undone step, returning whether there was a
+    /// step to redo.
+    pub fn redo(&mut self) -> bool {
+        if let Some(entry) = self.redo_history.pop() {
+            let egraph = std::mem::replace(&mut self.runner.egraph, entry.egraph);
+            let enode_ids = std::mem::replace(&mut self.enode_ids, entry.enode_ids);
+            let parse_state = std::mem::replace(&mut self.parse_state, entry.parse_state);
+            self.undo_history.push(EggvizRuntimeHistoryEntry {
+                egraph,
+                enode_ids,
+                parse_state,
+                applied_rules: entry.applied_rules,
+                step: entry.step,
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_history.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_history.is_empty()
+    }
+
+    /// Number of steps which led to the current e-graph (and can be undone).
+    pub fn history_len(&self) -> usize {
+        self.undo_history.len()
+    }
+
+    /// Rewrite rules applied in each step which led to the current e-graph, in
+    /// order. Steps which added a term (see [`EggvizRuntime::add_term`]) did
+    /// not apply any rewrite rules.
+    pub fn applied_rules(&self) -> impl Iterator<Item = &LinkedList<EggvizRewriteRuleLabel>> {
+        self.undo_history.iter().map(|entry| &entry.applied_rules)
+    }
+
+    /// Statistics of each rewrite rule, in the order the rules were defined.
+    ///
+    /// The statistics are accumulated over all steps performed on this
+    /// runtime, including steps which were undone since.
+    pub fn rule_stats(&self) -> Vec<(EggvizRewriteRuleLabel, EggvizRuleStats)> {
+        let mut rule_stats = self.sched_state.rule_stats();
+        self.rewrite_rules
+            .iter()
+            .map(|rewrite| {
+                let label = EggvizRewriteRuleLabel::from_str(rewrite.name.as_str()).unwrap();
+                let stats = rule_stats.remove(&label).unwrap_or_default();
+                (label, stats)
+            })
+            .collect()
+    }
+
+    /// Record the current session, i.e. the program, rewrite rules and the
+    /// steps which led to the current e-graph.
+    pub fn session(&self) -> EggvizSession {
+        EggvizSession {
+            program: self.program_str.clone(),
+            rewrite_rules: self.rewrite_rules_str.clone(),
+            steps: self
+                .undo_history
+                .iter()
+                .map(|entry| (entry.step.clone(), entry.applied_rules.clone()))
+                .collect(),
+            egraph_nodes: self.runner.egraph.total_number_of_nodes(),
+            egraph_classes: self.runner.egraph.number_of_classes(),
+        }
+    }
+
+    /// Restore a recorded session by constructing a fresh runtime and
+    /// replaying all of its steps.
+    ///
+    /// Each replayed step must succeed and apply the same rewrite rules as
+    /// recorded, and the resulting e-graph must be of the recorded size. Otherwise, a
+    /// [`EggvizRuntimeError::SessionMismatch`] is reported.
+    pub fn from_session(session: &EggvizSession) -> Result<Self, EggvizRuntimeError> {
+        let mut runtime = Self::new(
+            &session.program,
+            session
+                .rewrite_rules
+                .iter()
+                .map(|(label, left, direction, right)| (label.as_ref(), left, *direction, right)),
+        )?;
+
+        for (idx, (step, applied_rules)) in session.steps.iter().enumerate() {
+            let history_len = runtime.history_len();
+            let replayed_rules = match step.clone() {
+                EggvizSessionStep::RewriteRule(rule) => {
+                    Ok(runtime.rewrite_rule(rule).applied_rules)
+                }
+                EggvizSessionStep::RewriteRuleMatch(rule_match) => runtime
+                    .rewrite_rule_match(rule_match)
+                    .map(|report| report.applied_rules),
+                EggvizSessionStep::RewriteAuto => Ok(runtime.rewrite_auto().applied_rules),
+                EggvizSessionStep::RunUntilSaturated {
+                    iterations,
+                    node_limit,
+                } => {
+                    let limits = EggvizRunLimits {
+                        node_limit,
+                        ..EggvizRunLimits::iterations(iterations)
+                    };
+                    Ok(runtime.run_until_saturated(&limits).step.applied_rules)
+                }
+                EggvizSessionStep::Union(a, b) => {
+                    runtime.union(a, b).map(|report| report.applied_rules)
+                }
+                EggvizSessionStep::AddTerm(term) => {
+                    runtime.add_term(&term).map(|_| LinkedList::new())
+                }
+            }
+            .map_err(|e| {
+                EggvizRuntimeError::SessionMismatch(format!("step {} failed: {}", idx + 1, e))
+            })?;
+
+            // Every recorded step changed the e-graph, so it must have been
+            // recorded again when replaying it:
+            if runtime.history_len() != history_len + 1 || replayed_rules != *applied_rules {
+                return Err(EggvizRuntimeError::SessionMismatch(format!(
+                    "step {} applied the rewrite rules [{}], but [{}] were recorded.",
+                    idx + 1,
+                    replayed_rules
+                        .iter()
+                        .map(|rule| rule.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    applied_rules
+                        .iter()
+                        .map(|rule| rule.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )));
+            }
+        }
+
+        let egraph = &runtime.runner.egraph;
+        if (egraph.total_number_of_nodes(), egraph.number_of_classes())
+            != (session.egraph_nodes, session.egraph_classes)
+        {
+            return Err(EggvizRuntimeError::SessionMismatch(format!(
+                "the resulting e-graph has {} e-nodes in {} e-classes, but {} e-nodes in {} \
+                 e-classes were recorded.",
+                egraph.total_number_of_nodes(),
+                egraph.number_of_classes(),
+                session.egraph_nodes,
+                session.egraph_classes,
+            )));
+        }
+
+        Ok(runtime)
+    }
+
+    /// Add a term to the current e-graph, returning its (canonical) e-class.
+    ///
+    /// The term is validated against, and retained in, the program's parse
+    /// state, such that subsequent terms must be consistent with it. If the
+    /// term was not represented in the e-graph yet, adding it is recorded in
+    /// the history as a step without any applied rewrite rules. Undoing this
+    /// step also reverts the parse state.
+    pub fn add_term(&mut self, term: &str) -> Result<egg::Id, EggvizRuntimeError> {
+        let mut parse_state = self.parse_state.clone();
+        let expr = self.parse_term_with(&mut parse_state, term)?;
+        let prev_parse_state = std::mem::replace(&mut self.parse_state, parse_state);
+
+        let prev_egraph = self.runner.egraph.clone();
+        let id = self.runner.egraph.add_expr(&expr);
+        self.runner.egraph.rebuild();
+        if self.runner.egraph.total_number_of_nodes() != prev_egraph.total_number_of_nodes() {
+            self.record_step(
+                prev_egraph,
+                prev_parse_state,
+                LinkedList::new(),
+                EggvizSessionStep::AddTerm(term.to_string()),
+            );
+        }
+
+        Ok(self.runner.egraph.find(id))
+    }
+
+    /// Check whether two terms are represented in the current e-graph, and
+    /// whether they are equivalent, i.e. share an e-class.
+    pub fn are_equivalent(
+        &self,
+        lhs: &str,
+        rhs: &str,
+    ) -> Result<EggvizEquivalence, EggvizRuntimeError> {
+        let lhs_expr = self.parse_term(lhs)?;
+        let rhs_expr = self.parse_term(rhs)?;
+        let egraph = &self.runner.egraph;
+        Ok(EggvizEquivalence {
+            lhs: egraph.lookup_expr(&lhs_expr).map(|id| egraph.find(id)),
+            rhs: egraph.lookup_expr(&rhs_expr).map(|id| egraph.find(id)),
+        })
+    }
+
+    /// Explain why two terms are equivalent in the current e-graph, as a
+    /// sequence of rewrite rule applications transforming `lhs` into `rhs`.
+    pub fn explain_equivalence(
+        &self,
+        lhs: &str,
+        rhs: &str,
+    ) -> Result<EggvizExplanation, EggvizRuntimeError> {
+        let lhs_expr = self.parse_term(lhs)?;
+        let rhs_expr = self.parse_term(rhs)?;
+
+        // Egg panics when asked to explain terms which are not equivalent:
+        let lhs_id = self
+            .runner
+            .egraph
+            .lookup_expr(&lhs_expr)
+            .ok_or_else(|| EggvizRuntimeError::TermNotRepresented(lhs.to_string()))?;
+        let rhs_id = self
+            .runner
+            .egraph
+            .lookup_expr(&rhs_expr)
+            .ok_or_else(|| EggvizRuntimeError::TermNotRepresented(rhs.to_string()))?;
+        if lhs_id != rhs_id {
+            return Err(EggvizRuntimeError::TermsNotEquivalent(
+                lhs.to_string(),
+                rhs.to_string(),
+            ));
+        }
+
+        // Producing an explanation requires mutable access to the e-graph, but
+        // should not have any visible effect on it. Work on a copy instead:
+        let mut egraph = self.runner.egraph.clone();
+        EggvizExplanation::from_egg(egraph.explain_equivalence(&lhs_expr, &rhs_expr))
+    }
+
+    /// Extract the best term represented by an e-class according to the
+    /// passed cost function. When no e-class is specified, the term is
+    /// extracted from the e-class of the program's first root term.
+    pub fn extract(
+        &self,
+        eclass: Option<egg::Id>,
+        cost_function: &mut dyn EggvizCostFunction<P::Language>,
+    ) -> Result<EggvizExtraction, EggvizRuntimeError> {
+        let egraph = &self.runner.egraph;
+        let eclass = match eclass {
+            Some(id) if egraph.classes().any(|eclass| eclass.id == id) => id,
+            Some(id) => return Err(EggvizRuntimeError::UnknownEClass(id)),
+            None => egraph.find(self.runner.roots[0]),
+        };
+
+        let extractor = egg::Extractor::new(egraph, EggvizCostFunctionAdapter(cost_function));
+        let (cost, expr) = extractor.find_best(eclass);
+
+        Ok(EggvizExtraction {
+            eclass,
+            cost,
+            term: expr.to_string(),
+        })
+    }
+
+    /// Cost function as specified by the program, see
+    /// [`EggvizProgram::cost_function`].
+    pub fn program_cost_function(&self) -> Box<dyn EggvizCostFunction<P::Language>> {
+        self.program.cost_function(&self.parse_state)
+    }
+
+    /// Export the current e-graph in the Graphviz DOT format.
+    ///
+    /// E-classes are rendered as clusters of their e-nodes. E-nodes are
+    /// records with a port for each of their children, from which an edge
+    /// leads to the cluster of the child e-class. If `highlight_last_step` is
+    /// set, the e-nodes added and the e-classes created or merged in the most
+    /// recent step are highlighted.
+    pub fn dump_graph(&self, highlight_last_step: bool) -> String {
+        let egraph = &self.runner.egraph;
+        let mut dot = EggvizDot {
+            egraph,
+            data_to_string: P::analysis_data_to_string,
+            highlighted_nodes: HashSet::new(),
+            highlighted_classes: HashSet::new(),
+        };
+
+        if let Some(entry) = self.undo_history.last().filter(|_| highlight_last_step) {
+            let report =
+                EggvizStepReport::new(&entry.egraph, egraph, LinkedList::new(), Vec::new());
+            dot.highlighted_nodes = report
+                .added_nodes
+                .into_iter()
+                .map(|node| (node.eclass, node.function, node.children))
+                .collect();
+            dot.highlighted_classes = report
+                .added_classes
+                .into_iter()
+                .chain(report.merged_classes.into_iter().map(|(_, id)| id))
+                .collect();
+        }
+
+        dot.to_string()
+    }
+
+    /// Number of e-classes in the current e-graph.
+    pub fn number_of_classes(&self) -> usize {
+        self.runner.egraph.number_of_classes()
+    }
+
+    /// Current e-graph, as a map from e-class ids to the string
+    /// representation of the e-class' analysis data and its e-nodes. E-nodes
+    /// are keyed by an identifier which is unique and remains stable across
+    /// steps, and are given by their function name and child e-classes.
+    #[allow(clippy::type_complexity)]
+    pub fn current_graph(&self) -> HashMap<String, (String, HashMap<u64, (String, Vec<String>)>)> {
         self.runner
             .egraph
             .classes()
             .map(|eclass| {
                 (
                     eclass.id.to_string(),
-                    eclass
-                        .nodes
-                        .iter()
-                        .map(|enode| {
-                            let mut hasher = DefaultHasher::new();
-                            enode.hash(&mut hasher);
-                            (
-                                hasher.finish(),
+                    (
+                        P::analysis_data_to_string(&eclass.data),
+                        eclass
+                            .nodes
+                            .iter()
+                            .map(|enode| {
                                 (
-                                    enode.get_function_name().to_string(),
-                                    egg::Language::children(enode)
-                                        .iter()
-                                        .map(|id| id.to_string())
-                                        .collect(),
-                                ),
-                            )
-                        })
-                        .collect(),
+                                    self.enode_ids.get(enode).expect(
+                                        "E-node identifiers were not updated for the current e-graph",
+                                    ),
+                                    (
+                                        enode.get_function_name().to_string(),
+                                        egg::Language::children(enode)
+                                            .iter()
+                                            .map(|id| id.to_string())
+                                            .collect(),
+                                    ),
+                                )
+                            })
+                            .collect(),
+                    ),
                 )
             })
             .collect()
     }
 }
 
-// ----- Expose an Eggviz struct parametrized over the lispylang to JS -----
-#[wasm_bindgen]
-pub struct LispylangEggvizRuntime {
-    inner: EggvizRuntime<lispylang::Program>,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-#[wasm_bindgen]
-impl LispylangEggvizRuntime {
-    pub fn new(
-        program_str: &str,
-        rewrite_rules_js: Box<[js_sys::JsString]>,
-    ) -> Result<LispylangEggvizRuntime, String> {
-        Ok(LispylangEggvizRuntime {
-            inner: EggvizRuntime::new(
-                program_str,
-                rewrite_rules_js
+    fn runtime(program: &str, rules: &[(&str, &str)]) -> EggvizRuntime<lispylang::Program> {
+        EggvizRuntime::new(
+            program,
+            rules.iter().map(|(left, right)| {
+                (None::<&str>, *left, EggvizRewriteDirection::Forward, *right)
+            }),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn undo_redo_restore_snapshots() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        let initial = runtime.current_graph();
+        assert!(!runtime.can_undo());
+
+        assert!(!runtime
+            .rewrite_rule(EggvizRewriteRuleLabel::Indexed(0))
+            .applied_rules
+            .is_empty());
+        let rewritten = runtime.current_graph();
+        assert_ne!(initial, rewritten);
+        assert_eq!(runtime.history_len(), 1);
+
+        assert!(runtime.undo());
+        assert_eq!(runtime.current_graph(), initial);
+        assert!(!runtime.undo());
+
+        assert!(runtime.redo());
+        assert_eq!(runtime.current_graph(), rewritten);
+        assert!(!runtime.redo());
+        assert_eq!(
+            runtime.applied_rules().collect::<Vec<_>>(),
+            [&LinkedList::from([EggvizRewriteRuleLabel::Indexed(0)])]
+        );
+    }
+
+    #[test]
+    fn new_step_clears_redo_history() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        runtime.rewrite_rule(EggvizRewriteRuleLabel::Indexed(0));
+        assert!(runtime.undo());
+        assert!(runtime.can_redo());
+
+        assert_eq!(runtime.rewrite_auto().applied_rules.len(), 1);
+        assert!(!runtime.can_redo());
+        assert!(!runtime.redo());
+        assert_eq!(runtime.history_len(), 1);
+    }
+
+    #[test]
+    fn steps_without_effect_are_not_recorded() {
+        let mut runtime = runtime("(f a b)", &[("(g pa)", "(g pa)")]);
+        assert!(runtime
+            .rewrite_rule(EggvizRewriteRuleLabel::Indexed(0))
+            .applied_rules
+            .is_empty());
+        assert!(runtime.rewrite_auto().applied_rules.is_empty());
+        assert!(!runtime.can_undo());
+    }
+
+    #[test]
+    fn step_report_lists_added_nodes_and_matches() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        let report = runtime.rewrite_rule(EggvizRewriteRuleLabel::Indexed(0));
+        assert_eq!(
+            report.applied_rules,
+            LinkedList::from([EggvizRewriteRuleLabel::Indexed(0)])
+        );
+
+        // The single match binds both pattern variables, and the added e-node
+        // has its children swapped:
+        assert_eq!(report.matches.len(), 1);
+        let rule_match = &report.matches[0];
+        let bound: Vec<&str> = rule_match
+            .substitution
+            .iter()
+            .map(|(var, _)| var.as_str())
+            .collect();
+        assert_eq!(bound, ["?pa", "?pb"]);
+
+        assert_eq!(report.added_nodes.len(), 1);
+        let node = &report.added_nodes[0];
+        assert_eq!(node.eclass, runtime.runner.egraph.find(rule_match.eclass));
+        assert_eq!(node.function, "f");
+        assert_eq!(
+            node.children,
+            [rule_match.substitution[1].1, rule_match.substitution[0].1]
+        );
+    }
+
+    #[test]
+    fn step_report_lists_added_and_merged_classes() {
+        let mut runtime = runtime(
+            "(g (f a) b)",
+            &[("(f pa)", "pa"), ("(g pa pb)", "(g pa (h pb))")],
+        );
+
+        let report = runtime.rewrite_rule(EggvizRewriteRuleLabel::Indexed(1));
+        let mut functions: Vec<&str> = report
+            .added_nodes
+            .iter()
+            .map(|node| node.function.as_str())
+            .collect();
+        functions.sort();
+        assert_eq!(functions, ["g", "h"]);
+        let h_node = report
+            .added_nodes
+            .iter()
+            .find(|node| node.function == "h")
+            .unwrap();
+        assert!(report.added_classes.contains(&h_node.eclass));
+
+        let report = runtime.rewrite_rule(EggvizRewriteRuleLabel::Indexed(0));
+        assert!(report.added_nodes.is_empty());
+        assert!(report.added_classes.is_empty());
+        assert_eq!(report.merged_classes.len(), 1);
+        let (prev_id, id) = report.merged_classes[0];
+        assert_eq!(runtime.runner.egraph.find(prev_id), id);
+    }
+
+    #[test]
+    fn extract_cheapest_term() {
+        let mut runtime = runtime("(f (g a))", &[("(f (g pa))", "(h pa)")]);
+        runtime.rewrite_auto();
+
+        let extraction = runtime.extract(None, &mut egg::AstSize).unwrap();
+        assert_eq!(extraction.term, "(h a)");
+        assert_eq!(extraction.cost, 2.0);
+        assert_eq!(
+            extraction.eclass,
+            runtime.runner.egraph.find(runtime.runner.roots[0])
+        );
+
+        let extraction = runtime.extract(None, &mut egg::AstDepth).unwrap();
+        assert_eq!(extraction.term, "(h a)");
+        assert_eq!(extraction.cost, 2.0);
+    }
+
+    #[test]
+    fn extract_from_eclass() {
+        let runtime = runtime("(f (g a))", &[]);
+        let expr: egg::RecExpr<lispylang::Language> = "(g a)".parse().unwrap();
+        let eclass = runtime.runner.egraph.lookup_expr(&expr).unwrap();
+
+        let extraction = runtime.extract(Some(eclass), &mut egg::AstSize).unwrap();
+        assert_eq!(extraction.eclass, eclass);
+        assert_eq!(extraction.term, "(g a)");
+
+        assert!(matches!(
+            runtime.extract(Some(egg::Id::from(42)), &mut egg::AstSize),
+            Err(EggvizRuntimeError::UnknownEClass(id)) if id == egg::Id::from(42)
+        ));
+    }
+
+    #[test]
+    fn rewrite_single_rule_match() {
+        let mut runtime = runtime("(g (f a b) (f c d))", &[("(f pa pb)", "(f pb pa)")]);
+        let rule = EggvizRewriteRuleLabel::Indexed(0);
+        let rule_matches = runtime.preview_rule(&rule).unwrap();
+        assert_eq!(rule_matches.len(), 2);
+
+        let report = runtime.rewrite_rule_match(rule_matches[0].clone()).unwrap();
+        assert_eq!(report.applied_rules, LinkedList::from([rule.clone()]));
+        assert_eq!(report.matches.len(), 1);
+        assert_eq!(report.added_nodes.len(), 1);
+        assert_eq!(runtime.preview_rule(&rule).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn rewrite_stale_rule_match() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        let rule = EggvizRewriteRuleLabel::Indexed(0);
+        let initial_matches = runtime.preview_rule(&rule).unwrap();
+        runtime.rewrite_rule(rule.clone());
+        let new_match = runtime
+            .preview_rule(&rule)
+            .unwrap()
+            .into_iter()
+            .find(|m| {
+                !initial_matches
                     .iter()
-                    .step_by(2)
-                    .zip(rewrite_rules_js.iter().skip(1).step_by(2))
-                    .map(|(left, right)| {
-                        (
-                            None::<&str>,
-                            <js_sys::JsString as ToString>::to_string(left),
-                            <js_sys::JsString as ToString>::to_string(right),
-                        )
-                    }),
-            )
-            .map_err(|e| format!("{}", e))?,
-        })
+                    .any(|initial| initial.substitution == m.substitution)
+            })
+            .unwrap();
+
+        assert!(runtime.undo());
+        assert!(matches!(
+            runtime.rewrite_rule_match(new_match),
+            Err(EggvizRuntimeError::RuleMatchNotFound(_))
+        ));
+        assert!(matches!(
+            runtime.preview_rule(&EggvizRewriteRuleLabel::Indexed(1)),
+            Err(EggvizRuntimeError::UnknownRewriteRule(_))
+        ));
+    }
+
+    #[test]
+    fn preview_rule_leaves_egraph_untouched() {
+        let runtime = runtime("(g (f a b) (f c d))", &[("(f pa pb)", "(f pb pa)")]);
+        let graph = runtime.current_graph();
+        let rule_matches = runtime
+            .preview_rule(&EggvizRewriteRuleLabel::Indexed(0))
+            .unwrap();
+        assert_eq!(rule_matches.len(), 2);
+        for rule_match in &rule_matches {
+            let functions: Vec<&str> = graph[&rule_match.eclass.to_string()]
+                .1
+                .values()
+                .map(|(function, _)| function.as_str())
+                .collect();
+            assert_eq!(functions, ["f"]);
+        }
+        assert_eq!(runtime.current_graph(), graph);
+        assert!(!runtime.can_undo());
     }
 
-    pub fn rewrite_rule(&mut self, rule_label: &str) -> Result<bool, String> {
-        let parsed_label = EggvizRewriteRuleLabel::from_str(rule_label)
-            .map_err(|_| format!("Unable to parse rule label \"{}\"", rule_label))?;
-        Ok(self.inner.rewrite_rule(parsed_label))
+    #[test]
+    fn run_until_saturated() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        let report = runtime.run_until_saturated(&EggvizRunLimits::default());
+        assert!(matches!(report.stop_reason, egg::StopReason::Saturated));
+        assert_eq!(
+            report.step.applied_rules,
+            LinkedList::from([EggvizRewriteRuleLabel::Indexed(0)])
+        );
+        // The last iteration finds the rule's matches, but changes nothing:
+        let last = report.iterations.last().unwrap();
+        assert_eq!(last.applied, []);
+        assert_eq!(runtime.history_len(), 1);
     }
 
-    pub fn rewrite_auto(&mut self) -> Result<js_sys::Array, String> {
-        Ok(self
-            .inner
-            .rewrite_auto()
+    #[test]
+    fn run_until_iteration_limit() {
+        let mut runtime = runtime("(f a)", &[("(f pa)", "(f (g pa))")]);
+        let report = runtime
+            .run_until_saturated(&EggvizRunLimits::iterations(NonZeroUsize::new(3).unwrap()));
+        assert!(matches!(
+            report.stop_reason,
+            egg::StopReason::IterationLimit(_)
+        ));
+        assert_eq!(report.iterations.len(), 3);
+        assert_eq!(report.step.applied_rules.len(), 3);
+    }
+
+    #[test]
+    fn conditional_rewrite_rule() {
+        let mut runtime = runtime(
+            "(g (f a b) (f a a))",
+            &[("(f pa pb)", "(h pa) if (!= pa pb)")],
+        );
+        let rule = EggvizRewriteRuleLabel::Indexed(0);
+        let rule_matches = runtime.preview_rule(&rule).unwrap();
+        assert_eq!(rule_matches.len(), 2);
+        let (equal, distinct): (Vec<_>, Vec<_>) = rule_matches
             .into_iter()
-            .map(|rewrite_rule| js_sys::JsString::from(rewrite_rule.to_string()))
-            .collect())
+            .partition(|m| m.substitution[0].1 == m.substitution[1].1);
+
+        assert!(matches!(
+            runtime.rewrite_rule_match(equal[0].clone()),
+            Err(EggvizRuntimeError::RuleConditionFailed(_))
+        ));
+        assert!(!runtime.can_undo());
+
+        let report = runtime.rewrite_rule_match(distinct[0].clone()).unwrap();
+        assert_eq!(report.applied_rules, LinkedList::from([rule.clone()]));
+
+        // Applying the rule everywhere only rewrites where the condition holds:
+        assert!(runtime.rewrite_rule(rule).applied_rules.is_empty());
     }
 
-    pub fn dump_graph(&self) -> String {
-        self.inner.dump_graph()
+    #[test]
+    fn bidirectional_rewrite_rule() {
+        let mut runtime = EggvizRuntime::<lispylang::Program>::new(
+            "(g (f a b) (h c c))",
+            [(
+                None::<&str>,
+                "(f pa pb)",
+                EggvizRewriteDirection::Bidirectional,
+                "(h pb pa) if (!= pa pb)",
+            )],
+        )
+        .unwrap();
+        let forward = EggvizRewriteRuleLabel::Indexed(0);
+        let reverse = EggvizRewriteRuleLabel::IndexedReverse(0);
+        assert_eq!(runtime.preview_rule(&forward).unwrap().len(), 1);
+
+        // The reverse direction checks the condition as well:
+        let reverse_matches = runtime.preview_rule(&reverse).unwrap();
+        assert_eq!(reverse_matches.len(), 1);
+        assert!(matches!(
+            runtime.rewrite_rule_match(reverse_matches[0].clone()),
+            Err(EggvizRuntimeError::RuleConditionFailed(_))
+        ));
+
+        let report = runtime.rewrite_rule(forward);
+        assert_eq!(report.added_nodes.len(), 1);
+        assert_eq!(runtime.preview_rule(&reverse).unwrap().len(), 2);
     }
 
-    pub fn current_graph(&self) -> js_sys::Map {
-        let graph = self.inner.current_graph();
+    #[test]
+    fn duplicate_rule_labels_are_reported_with_other_diagnostics() {
+        let res = EggvizRuntime::<lispylang::Program>::new(
+            "(f a b)",
+            [
+                (
+                    Some("comm"),
+                    "(f pa pb)",
+                    EggvizRewriteDirection::Forward,
+                    "(f pb pa)",
+                ),
+                (
+                    Some("comm"),
+                    "(f pa pb)",
+                    EggvizRewriteDirection::Forward,
+                    "(f pa)",
+                ),
+                (
+                    Some("bad"),
+                    "(f pa pb)",
+                    EggvizRewriteDirection::Forward,
+                    "(f pa pc)",
+                ),
+            ],
+        );
+        let diagnostics = match res {
+            Err(EggvizRuntimeError::ParseErrors(diagnostics)) => diagnostics,
+            _ => panic!("Expected parse errors"),
+        };
+        let messages: Vec<String> = diagnostics.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("Rewrite rule rwr:comm was duplicated."));
+        assert!(messages[1].contains("'pc'"));
+    }
 
-        let eclasses_map = js_sys::Map::new();
-        for (eclass_id, enodes) in graph.into_iter() {
-            let enode_map = js_sys::Map::new();
-            for (enode_hash, (enode_label, children)) in enodes.into_iter() {
-                let enode_children: js_sys::Array =
-                    children.into_iter().map(js_sys::JsString::from).collect();
+    #[test]
+    fn equivalence_of_terms() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        let equivalence = runtime.are_equivalent("(f a b)", "(f b a)").unwrap();
+        assert!(equivalence.lhs.is_some());
+        assert_eq!(equivalence.rhs, None);
+        assert!(!equivalence.equivalent());
+
+        runtime.rewrite_auto();
+        let equivalence = runtime.are_equivalent("(f a b)", "(f b a)").unwrap();
+        assert!(equivalence.equivalent());
+        assert_eq!(
+            equivalence.lhs,
+            Some(runtime.runner.egraph.find(runtime.runner.roots[0]))
+        );
 
-                let enode = js_sys::Map::new();
-                enode.set(
-                    &js_sys::JsString::from("label"),
-                    &js_sys::JsString::from(enode_label),
-                );
-                enode.set(&js_sys::JsString::from("children"), &enode_children);
+        let equivalence = runtime.are_equivalent("a", "b").unwrap();
+        assert!(equivalence.lhs.is_some() && equivalence.rhs.is_some());
+        assert!(!equivalence.equivalent());
+
+        assert!(matches!(
+            runtime.are_equivalent("(f a)", "a"),
+            Err(EggvizRuntimeError::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn add_term_records_step() {
+        let mut runtime = runtime("(f a b)", &[]);
+        let id = runtime.add_term("(f a b)").unwrap();
+        assert_eq!(id, runtime.runner.egraph.find(runtime.runner.roots[0]));
+        assert!(!runtime.can_undo());
+
+        let id = runtime.add_term("(g a)").unwrap();
+        assert_eq!(runtime.are_equivalent("(g a)", "a").unwrap().lhs, Some(id));
+        assert_eq!(runtime.history_len(), 1);
+        assert!(runtime.applied_rules().all(|rules| rules.is_empty()));
+    }
+
+    #[test]
+    fn undo_restores_parse_state() {
+        let mut runtime = runtime("(f a b)", &[]);
+        runtime.add_term("(k a)").unwrap();
+        assert!(matches!(
+            runtime.add_term("(k a b)"),
+            Err(EggvizRuntimeError::ParseError(_))
+        ));
 
-                enode_map.set(&js_sys::Number::from(enode_hash as u32), &enode);
+        assert!(runtime.undo());
+        assert!(runtime.add_term("(k a b)").is_ok());
+        assert!(runtime.undo());
+
+        // Redoing the step retains the symbol's arity again:
+        assert!(runtime.redo());
+        assert!(matches!(
+            runtime.add_term("(k a)"),
+            Err(EggvizRuntimeError::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn union_restores_congruence() {
+        let mut runtime = runtime("(g (f a) (f b))", &[]);
+        let ids = runtime.are_equivalent("a", "b").unwrap();
+        let (a, b) = (ids.lhs.unwrap(), ids.rhs.unwrap());
+
+        let report = runtime.union(a, b).unwrap();
+        assert_eq!(
+            report.applied_rules,
+            LinkedList::from([EggvizRewriteRuleLabel::Union])
+        );
+        assert!(report.added_nodes.is_empty());
+        assert_eq!(report.merged_classes.len(), 2);
+        assert!(runtime
+            .are_equivalent("(f a)", "(f b)")
+            .unwrap()
+            .equivalent());
+
+        // Merging already equivalent e-classes is no step:
+        let ids = runtime.are_equivalent("a", "b").unwrap();
+        let report = runtime.union(ids.lhs.unwrap(), ids.rhs.unwrap()).unwrap();
+        assert!(report.applied_rules.is_empty());
+        assert_eq!(runtime.history_len(), 1);
+
+        assert!(runtime.undo());
+        assert!(!runtime.are_equivalent("a", "b").unwrap().equivalent());
+        assert!(matches!(
+            runtime.union(a, egg::Id::from(42)),
+            Err(EggvizRuntimeError::UnknownEClass(id)) if id == egg::Id::from(42)
+        ));
+    }
+
+    fn session_steps(runtime: &EggvizRuntime<lispylang::Program>) -> Vec<EggvizSessionStep> {
+        runtime
+            .session()
+            .steps
+            .into_iter()
+            .map(|(step, _)| step)
+            .collect()
+    }
+
+    fn assert_session_round_trip(runtime: &EggvizRuntime<lispylang::Program>) {
+        let session = runtime.session();
+        let restored = EggvizRuntime::<lispylang::Program>::from_session(&session).unwrap();
+        assert_eq!(restored.session(), session);
+        assert_eq!(restored.current_graph(), runtime.current_graph());
+        assert_eq!(restored.history_len(), runtime.history_len());
+    }
+
+    #[test]
+    fn session_round_trip() {
+        let mut runtime = runtime(
+            "(g (f a b) c)",
+            &[("(f pa pb)", "(f pb pa)"), ("(h pa)", "(k pa)")],
+        );
+        runtime.rewrite_rule(EggvizRewriteRuleLabel::Indexed(0));
+        runtime.add_term("(h c)").unwrap();
+        let rule_match = runtime
+            .preview_rule(&EggvizRewriteRuleLabel::Indexed(1))
+            .unwrap()
+            .remove(0);
+        runtime.rewrite_rule_match(rule_match.clone()).unwrap();
+        let ids = runtime.are_equivalent("a", "b").unwrap();
+        let (a, b) = (ids.lhs.unwrap(), ids.rhs.unwrap());
+        runtime.union(a, b).unwrap();
+        runtime.add_term("(h a)").unwrap();
+        runtime.rewrite_auto();
+        runtime.add_term("(h (f c c))").unwrap();
+        runtime.run_until_saturated(&EggvizRunLimits::default());
+
+        let steps = session_steps(&runtime);
+        assert_eq!(steps.len(), runtime.history_len());
+        assert_eq!(
+            steps[..7],
+            [
+                EggvizSessionStep::RewriteRule(EggvizRewriteRuleLabel::Indexed(0)),
+                EggvizSessionStep::AddTerm("(h c)".to_string()),
+                EggvizSessionStep::RewriteRuleMatch(rule_match),
+                EggvizSessionStep::Union(a, b),
+                EggvizSessionStep::AddTerm("(h a)".to_string()),
+                EggvizSessionStep::RewriteAuto,
+                EggvizSessionStep::AddTerm("(h (f c c))".to_string()),
+            ]
+        );
+        assert!(matches!(
+            steps[7],
+            EggvizSessionStep::RunUntilSaturated { .. }
+        ));
+
+        assert_session_round_trip(&runtime);
+    }
+
+    #[test]
+    fn session_round_trip_after_undo() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        runtime.add_term("(f b c)").unwrap();
+        runtime.rewrite_auto();
+        assert!(runtime.undo());
+
+        assert_eq!(
+            session_steps(&runtime),
+            [EggvizSessionStep::AddTerm("(f b c)".to_string())]
+        );
+        assert_session_round_trip(&runtime);
+    }
+
+    #[test]
+    fn tampered_session_is_rejected() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        runtime.rewrite_auto();
+        let ids = runtime.are_equivalent("a", "b").unwrap();
+        runtime.union(ids.lhs.unwrap(), ids.rhs.unwrap()).unwrap();
+        let session = runtime.session();
+
+        let restore = |session: &EggvizSession| {
+            matches!(
+                EggvizRuntime::<lispylang::Program>::from_session(session),
+                Err(EggvizRuntimeError::SessionMismatch(_))
+            )
+        };
+
+        let mut tampered = session.clone();
+        tampered.steps[0].1 = LinkedList::new();
+        assert!(restore(&tampered));
+
+        let mut tampered = session.clone();
+        tampered.steps[1].0 = EggvizSessionStep::Union(ids.lhs.unwrap(), egg::Id::from(42));
+        assert!(restore(&tampered));
+
+        let mut tampered = session.clone();
+        tampered.steps.swap(0, 1);
+        assert!(restore(&tampered));
+
+        let mut tampered = session;
+        tampered.egraph_nodes += 1;
+        assert!(restore(&tampered));
+    }
+
+    #[test]
+    fn session_records_iterations_run() {
+        let mut saturating = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        let report = saturating.run_until_saturated(&EggvizRunLimits::default());
+        assert!(matches!(report.stop_reason, egg::StopReason::Saturated));
+        assert_eq!(
+            session_steps(&saturating),
+            [EggvizSessionStep::RunUntilSaturated {
+                iterations: NonZeroUsize::new(report.iterations.len()).unwrap(),
+                node_limit: EggvizRunLimits::default().node_limit,
+            }]
+        );
+        assert_session_round_trip(&saturating);
+
+        // Runs stopped by a limit other than the iteration limit are replayed
+        // with the number of iterations they ran, rather than the limits they
+        // were started with:
+        let mut growing = runtime("(f a)", &[("(f pa)", "(f (g pa))")]);
+        let limits = EggvizRunLimits {
+            node_limit: 8,
+            ..EggvizRunLimits::default()
+        };
+        let report = growing.run_until_saturated(&limits);
+        assert!(matches!(report.stop_reason, egg::StopReason::NodeLimit(_)));
+        assert_eq!(
+            session_steps(&growing),
+            [EggvizSessionStep::RunUntilSaturated {
+                iterations: NonZeroUsize::new(report.iterations.len()).unwrap(),
+                node_limit: 8,
+            }]
+        );
+        assert_session_round_trip(&growing);
+    }
+
+    #[test]
+    fn dump_graph_highlights_last_step() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        let dot = runtime.dump_graph(true);
+        assert!(dot.starts_with("digraph egraph {"));
+        assert_eq!(dot.matches("subgraph cluster_").count(), 3);
+        assert!(!dot.contains("orange"));
+
+        runtime.run_until_saturated(&EggvizRunLimits::default());
+        let dot = runtime.dump_graph(true);
+        assert_eq!(dot.matches("fillcolor=orange").count(), 1);
+        assert!(dot.contains("{f|{<c0> 0|<c1> 1}}"));
+        assert!(!runtime.dump_graph(false).contains("orange"));
+    }
+
+    /// Function names of the e-nodes in the current e-graph, keyed by their
+    /// identifiers, which must be unique across all e-classes.
+    fn enode_functions(runtime: &EggvizRuntime<lispylang::Program>) -> HashMap<u64, String> {
+        let mut functions = HashMap::new();
+        for (_, enodes) in runtime.current_graph().into_values() {
+            for (id, (function, _)) in enodes {
+                assert!(functions.insert(id, function).is_none());
             }
-            eclasses_map.set(&js_sys::JsString::from(eclass_id), &enode_map);
         }
-        eclasses_map
+        functions
+    }
+
+    #[test]
+    fn enode_ids_are_stable_across_steps() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        let before = enode_functions(&runtime);
+        assert_eq!(before.len(), 3);
+
+        runtime.rewrite_rule(EggvizRewriteRuleLabel::Indexed(0));
+        let after = enode_functions(&runtime);
+        assert_eq!(after.len(), 4);
+        for (id, function) in before.iter() {
+            assert_eq!(after.get(id), Some(function));
+        }
+
+        // E-nodes which become congruent keep the oldest identifier:
+        let ids = runtime.are_equivalent("a", "b").unwrap();
+        runtime.union(ids.lhs.unwrap(), ids.rhs.unwrap()).unwrap();
+        let merged = enode_functions(&runtime);
+        assert_eq!(merged.len(), 3);
+        for (id, function) in merged.iter() {
+            assert_eq!(before.get(id), Some(function));
+        }
     }
-}
 
-#[wasm_bindgen(start)]
-pub fn startup() -> Result<(), JsValue> {
-    // This provides better error messages.
-    console_error_panic_hook::set_once();
+    #[test]
+    fn enode_ids_are_stable_across_undo_and_redo() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        let before = runtime.current_graph();
+        runtime.rewrite_rule(EggvizRewriteRuleLabel::Indexed(0));
+        runtime.add_term("(g a)").unwrap();
+        let after = runtime.current_graph();
+
+        assert!(runtime.undo());
+        assert!(runtime.undo());
+        assert_eq!(runtime.current_graph(), before);
+        assert!(runtime.redo());
+        assert!(runtime.redo());
+        assert_eq!(runtime.current_graph(), after);
+    }
 
-    console::log_1(&JsValue::from_str("eggviz WASM module initialized!"));
+    #[test]
+    fn enode_ids_are_stable_across_session_replay() {
+        let mut runtime = runtime("(f a b)", &[("(f pa pb)", "(f pb pa)")]);
+        runtime.add_term("(g a)").unwrap();
+        runtime.rewrite_rule(EggvizRewriteRuleLabel::Indexed(0));
+        let ids = runtime.are_equivalent("a", "b").unwrap();
+        runtime.union(ids.lhs.unwrap(), ids.rhs.unwrap()).unwrap();
+        runtime.add_term("(g b)").unwrap();
+
+        let restored =
+            EggvizRuntime::<lispylang::Program>::from_session(&runtime.session()).unwrap();
+        assert_eq!(restored.current_graph(), runtime.current_graph());
+    }
 
-    Ok(())
+    #[test]
+    fn rule_stats_count_searches_matches_and_changes() {
+        let mut runtime = runtime(
+            "(f a (g a))",
+            &[("(f pa pb)", "(f pb pa)"), ("(g pa)", "pa")],
+        );
+        let stats = |runtime: &EggvizRuntime<lispylang::Program>, idx: usize| {
+            let (label, stats) = runtime.rule_stats().remove(idx);
+            assert_eq!(label, EggvizRewriteRuleLabel::Indexed(idx));
+            (
+                stats.searches,
+                stats.matches,
+                stats.applications,
+                stats.nodes_added,
+                stats.classes_added,
+            )
+        };
+
+        runtime.rewrite_rule(EggvizRewriteRuleLabel::Indexed(0));
+        assert_eq!(stats(&runtime, 0), (1, 1, 1, 1, 0));
+        assert_eq!(stats(&runtime, 1), (0, 0, 0, 0, 0));
+
+        // Matches which don't change the e-graph are not counted as
+        // applications:
+        runtime.rewrite_rule(EggvizRewriteRuleLabel::Indexed(0));
+        assert_eq!(stats(&runtime, 0), (2, 3, 1, 1, 0));
+
+        // Merging e-classes is a net loss of e-classes:
+        runtime.rewrite_rule(EggvizRewriteRuleLabel::Indexed(1));
+        assert_eq!(stats(&runtime, 1), (1, 1, 1, 0, -1));
+
+        // Statistics are retained when undoing steps:
+        assert!(runtime.undo());
+        assert_eq!(stats(&runtime, 1), (1, 1, 1, 0, -1));
+    }
 }
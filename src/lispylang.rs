@@ -2,13 +2,26 @@ use std::collections::{hash_map::Entry, HashMap};
 use std::iter::Peekable;
 use std::str::Chars;
 
+use crate::EggvizCostFunction;
 use crate::EggvizLanguage;
 use crate::EggvizProgram;
+use crate::EggvizProgramParseContext;
 use crate::EggvizProgramParseError;
+use crate::EggvizRewriteCondition;
 use crate::EggvizRewriteRule;
 use crate::EggvizRewriteRuleLabel;
+use crate::EggvizRewriteRuleSide;
 
 const GENERIC_IDENTIFIER: &str = "p";
+const COST_DECLARATION: &str = "@cost";
+const TERM_DECLARATION: &str = "@term";
+/// Name of the program's unnamed root term, if any.
+const DEFAULT_ROOT_NAME: &str = "main";
+const CONDITION_IDENTIFIER: &str = "if";
+/// Function symbols evaluated on integer literals by [`ConstantFolding`].
+const ADDITION: &str = "+";
+const MULTIPLICATION: &str = "*";
+const SUBTRACTION: &str = "-";
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
 pub struct Language {
@@ -18,7 +31,7 @@ pub struct Language {
 
 impl std::fmt::Display for Language {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        std::fmt::Debug::fmt(self, f)
+        f.write_str(&self.function)
     }
 }
 
@@ -60,52 +73,217 @@ impl EggvizLanguage for Language {
 }
 
 pub struct Program {
-    root: Term,
+    roots: Vec<(String, Term)>,
 }
 
 impl EggvizProgram for Program {
     type Language = Language;
     type RewriteRule = RewriteRule;
     type ParseState = ArityChecker;
+    type Analysis = ConstantFolding;
 
-    fn parse_str(input: &str) -> Result<(Self, ArityChecker), EggvizProgramParseError> {
-        let mut arity_checker = ArityChecker::new();
-        let root = Parser::parse(input, true, &mut arity_checker)?;
-
-        Ok((Program { root }, arity_checker))
+    fn parse_str(
+        input: &str,
+        arity_checker: &mut ArityChecker,
+        diagnostics: &mut Vec<EggvizProgramParseError>,
+    ) -> Option<Self> {
+        match Parser::parse_program(input, arity_checker) {
+            Ok(roots) => Some(Program { roots }),
+            Err(errors) => {
+                diagnostics.extend(errors);
+                None
+            }
+        }
     }
 
     fn parse_rewrite_rule(
-        &self,
         arity_checker: &mut ArityChecker,
-        _label: &EggvizRewriteRuleLabel,
+        label: &EggvizRewriteRuleLabel,
         left: &str,
         right: &str,
-    ) -> Result<Self::RewriteRule, EggvizProgramParseError> {
-        // TODO: use label!
-        RewriteRule::from_str(left, right, arity_checker)
+        diagnostics: &mut Vec<EggvizProgramParseError>,
+    ) -> Option<Self::RewriteRule> {
+        match RewriteRule::from_str(label, left, right, arity_checker) {
+            Ok(rewrite_rule) => Some(rewrite_rule),
+            Err(errors) => {
+                diagnostics.extend(errors);
+                None
+            }
+        }
+    }
+
+    fn parse_term(
+        &self,
+        arity_checker: &mut ArityChecker,
+        input: &str,
+    ) -> Result<String, EggvizProgramParseError> {
+        Parser::parse(input, true, arity_checker)
+            .map(|term| term.to_egg())
+            .map_err(|mut errors| errors.remove(0))
+    }
+
+    fn cost_function(&self, arity_checker: &ArityChecker) -> Box<dyn EggvizCostFunction<Language>> {
+        Box::new(SymbolCost {
+            costs: arity_checker.costs.clone(),
+        })
+    }
+
+    fn analysis_data_to_string(constant: &Option<Constant>) -> String {
+        match constant {
+            Some(Constant::Value(value)) => format!("= {}", value),
+            Some(Constant::Conflict) => "= conflicting constants".to_string(),
+            None => String::new(),
+        }
+    }
+
+    fn roots_to_egg(&self) -> Vec<(String, String)> {
+        self.roots
+            .iter()
+            .map(|(name, term)| (name.clone(), term.to_egg()))
+            .collect()
+    }
+}
+
+/// Cost function summing up the declared costs of all function symbols in a
+/// term. Symbols without a declared cost have a cost of 1.
+pub struct SymbolCost {
+    costs: HashMap<String, f64>,
+}
+
+impl EggvizCostFunction<Language> for SymbolCost {
+    fn cost(&mut self, enode: &Language, child_costs: &[f64]) -> f64 {
+        self.costs.get(&enode.function).copied().unwrap_or(1.0) + child_costs.iter().sum::<f64>()
+    }
+}
+
+/// E-class analysis folding arithmetic on integer literals. The value of each
+/// e-class is known if it contains an integer literal, or an addition,
+/// multiplication or subtraction of e-classes with known values. E-classes with
+/// a known value are merged with the e-class of the corresponding literal.
+#[derive(Clone, Default)]
+pub struct ConstantFolding;
+
+/// Known value of an e-class under [`ConstantFolding`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Constant {
+    Value(i64),
+    /// The e-class was merged with an e-class of a different value, which can
+    /// only be caused by the program's rewrite rules. Such e-classes don't
+    /// take part in folding anymore.
+    Conflict,
+}
+
+impl ConstantFolding {
+    fn fold(function: &str, args: &[i64]) -> Option<i64> {
+        let (first, rest) = args.split_first()?;
+        match function {
+            ADDITION => rest
+                .iter()
+                .try_fold(*first, |acc, arg| acc.checked_add(*arg)),
+            MULTIPLICATION => rest
+                .iter()
+                .try_fold(*first, |acc, arg| acc.checked_mul(*arg)),
+            SUBTRACTION if rest.is_empty() => first.checked_neg(),
+            SUBTRACTION => rest
+                .iter()
+                .try_fold(*first, |acc, arg| acc.checked_sub(*arg)),
+            _ => None,
+        }
+    }
+}
+
+impl egg::Analysis<Language> for ConstantFolding {
+    type Data = Option<Constant>;
+
+    fn make(egraph: &egg::EGraph<Language, Self>, enode: &Language) -> Self::Data {
+        if enode.children.is_empty() {
+            return Variable::parse_integer(&enode.function)
+                .ok()
+                .flatten()
+                .map(Constant::Value);
+        }
+        let args = enode
+            .children
+            .iter()
+            .map(|child| match egraph[*child].data {
+                Some(Constant::Value(value)) => Some(value),
+                _ => None,
+            })
+            .collect::<Option<Vec<i64>>>()?;
+        Self::fold(&enode.function, &args).map(Constant::Value)
+    }
+
+    fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> egg::DidMerge {
+        egg::merge_option(a, b, |a, b| match (*a, b) {
+            (a, b) if a == b => egg::DidMerge(false, false),
+            (Constant::Conflict, _) => egg::DidMerge(false, true),
+            (_, Constant::Conflict) => {
+                *a = Constant::Conflict;
+                egg::DidMerge(true, false)
+            }
+            _ => {
+                *a = Constant::Conflict;
+                egg::DidMerge(true, true)
+            }
+        })
     }
 
-    fn to_egg(&self) -> String {
-        self.root.to_egg()
+    fn modify(egraph: &mut egg::EGraph<Language, Self>, id: egg::Id) {
+        if let Some(Constant::Value(value)) = egraph[id].data {
+            let literal = egraph.add(Language {
+                function: value.to_string(),
+                children: Vec::new(),
+            });
+            // Plain unions are not supported with explanations enabled:
+            egraph.union_trusted(id, literal, EggvizRewriteRuleLabel::Analysis.to_string());
+        }
     }
 }
 
 pub struct RewriteRule {
     left: Term,
     right: Term,
+    condition: Option<Condition>,
 }
 
 impl RewriteRule {
     fn from_str(
+        label: &EggvizRewriteRuleLabel,
         l: &str,
         r: &str,
         arity_checker: &mut ArityChecker,
-    ) -> Result<RewriteRule, EggvizProgramParseError> {
-        Ok(RewriteRule {
-            left: Parser::parse(l, false, arity_checker)?,
-            right: Parser::parse(r, false, arity_checker)?,
-        })
+    ) -> Result<RewriteRule, Vec<EggvizProgramParseError>> {
+        let in_rule = |side| {
+            move |errors: Vec<EggvizProgramParseError>| {
+                errors
+                    .into_iter()
+                    .map(|e| EggvizProgramParseError {
+                        context: e
+                            .context
+                            .map(|context| context.in_rewrite_rule(label, side)),
+                        ..e
+                    })
+                    .collect::<Vec<_>>()
+            }
+        };
+        // Parse both sides, even if the left one fails, to report all errors:
+        let left =
+            Parser::parse(l, false, arity_checker).map_err(in_rule(EggvizRewriteRuleSide::Left));
+        let right = Parser::parse_rewrite_right(r, arity_checker)
+            .map_err(in_rule(EggvizRewriteRuleSide::Right));
+        match (left, right) {
+            (Ok(left), Ok((right, condition))) => Ok(RewriteRule {
+                left,
+                right,
+                condition,
+            }),
+            (left, right) => Err(left
+                .err()
+                .into_iter()
+                .chain(right.err())
+                .flatten()
+                .collect()),
+        }
     }
 }
 
@@ -117,6 +295,34 @@ impl EggvizRewriteRule for RewriteRule {
     fn right_to_egg(&self) -> String {
         self.right.to_egg()
     }
+
+    fn condition_to_egg(&self) -> Option<EggvizRewriteCondition> {
+        self.condition.as_ref().map(Condition::to_egg)
+    }
+}
+
+/// Side condition of a rewrite rule, written after the right-hand side as
+/// `if <condition>`, e.g. `1 if (!= pa 0)`.
+pub enum Condition {
+    Equal(Term, Term),
+    NotEqual(Term, Term),
+    Not(Box<Condition>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+}
+
+impl Condition {
+    pub fn to_egg(&self) -> EggvizRewriteCondition {
+        match self {
+            Condition::Equal(l, r) => EggvizRewriteCondition::Equal(l.to_egg(), r.to_egg()),
+            Condition::NotEqual(l, r) => EggvizRewriteCondition::NotEqual(l.to_egg(), r.to_egg()),
+            Condition::Not(c) => EggvizRewriteCondition::Not(Box::new(c.to_egg())),
+            Condition::And(cs) => {
+                EggvizRewriteCondition::And(cs.iter().map(Self::to_egg).collect())
+            }
+            Condition::Or(cs) => EggvizRewriteCondition::Or(cs.iter().map(Self::to_egg).collect()),
+        }
+    }
 }
 
 pub struct Function {
@@ -159,6 +365,7 @@ impl Term {
 pub enum Variable {
     Concrete(String),
     Generic(String),
+    Integer(i64),
 }
 
 impl Variable {
@@ -166,6 +373,23 @@ impl Variable {
         match self {
             Variable::Concrete(s) => s.to_string(),
             Variable::Generic(s) => format!("?{}", s),
+            Variable::Integer(i) => i.to_string(),
+        }
+    }
+
+    /// Interpret a name as an integer literal, if it consists of decimal
+    /// digits with an optional sign. Fails if the literal is out of range.
+    fn parse_integer(name: &str) -> Result<Option<i64>, EggvizProgramParseError> {
+        let digits = name.strip_prefix(['+', '-']).unwrap_or(name);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            Ok(None)
+        } else {
+            name.parse().map(Some).map_err(|_| {
+                EggvizProgramParseError::context_less_owned(format!(
+                    "Integer literal '{}' is out of range.",
+                    name
+                ))
+            })
         }
     }
 }
@@ -173,12 +397,35 @@ impl Variable {
 #[derive(Clone)]
 pub struct ArityChecker {
     map: HashMap<String, usize>,
+    costs: HashMap<String, f64>,
+}
+
+impl Default for ArityChecker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ArityChecker {
     pub fn new() -> ArityChecker {
         ArityChecker {
             map: HashMap::new(),
+            costs: HashMap::new(),
+        }
+    }
+
+    pub fn declare_cost(&mut self, name: &str, cost: f64) -> Result<(), EggvizProgramParseError> {
+        match self.costs.entry(name.to_string()) {
+            Entry::Occupied(e) => Err(EggvizProgramParseError::context_less_owned(format!(
+                "Cannot declare a cost of {} for symbol '{}', because it was already declared with a cost of {}.",
+                cost,
+                name,
+                e.get()
+            ))),
+            Entry::Vacant(v) => {
+                v.insert(cost);
+                Ok(())
+            }
         }
     }
 
@@ -205,30 +452,377 @@ impl ArityChecker {
     }
 }
 
+/// Byte offset and length of a token.
+type Span = (usize, usize);
+
 pub struct Parser<'a> {
+    source: &'a str,
     input: Peekable<Chars<'a>>,
+    /// Byte offset of the next character in `input`.
+    offset: usize,
+    /// Span of the most recently consumed token.
+    token_span: Span,
+    pushback: Option<(Token, Span)>,
+    /// Parenthesis nesting depth after the most recently read token.
+    depth: usize,
+    /// Whether the end of the input has been reached.
+    exhausted: bool,
+    /// Errors which have been recovered from.
+    errors: Vec<EggvizProgramParseError>,
 }
 
-impl Parser<'_> {
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Parser<'a> {
+        Parser {
+            source,
+            input: source.chars().peekable(),
+            offset: 0,
+            token_span: (0, 0),
+            pushback: None,
+            depth: 0,
+            exhausted: false,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Skip tokens until the parenthesis nesting depth is back at `depth`,
+    /// such that parsing can continue after an error. Returns `false` if the
+    /// end of the input was reached instead.
+    fn recover(&mut self, depth: usize) -> bool {
+        while self.depth > depth && !self.exhausted {
+            self.consume();
+        }
+        !self.exhausted
+    }
+
+    /// Combine the result of parsing with all errors recovered from.
+    fn finish<T>(
+        self,
+        result: Result<T, EggvizProgramParseError>,
+    ) -> Result<T, Vec<EggvizProgramParseError>> {
+        let mut errors = self.errors;
+        match result {
+            Ok(value) if errors.is_empty() => Ok(value),
+            Ok(_) => Err(errors),
+            Err(e) => {
+                errors.push(e);
+                Err(errors)
+            }
+        }
+    }
+
+    /// Span of the most recently consumed token.
+    fn token_span(&self) -> Span {
+        self.token_span
+    }
+
+    fn push_back(&mut self, token: Token) {
+        self.pushback = Some((token, self.token_span));
+    }
+
+    /// Error located at the most recently consumed token.
+    fn error(&self, msg: impl AsRef<str>) -> EggvizProgramParseError {
+        self.error_owned(msg.as_ref().to_string())
+    }
+
+    fn error_owned(&self, msg: String) -> EggvizProgramParseError {
+        self.error_at(self.token_span, msg)
+    }
+
+    fn error_at(&self, span: Span, msg: String) -> EggvizProgramParseError {
+        self.locate(span, EggvizProgramParseError::context_less_owned(msg))
+    }
+
+    /// Attach the given span to an error which does not have a context yet.
+    fn locate(&self, span: Span, e: EggvizProgramParseError) -> EggvizProgramParseError {
+        EggvizProgramParseError {
+            context: e.context.or_else(|| {
+                Some(EggvizProgramParseContext::program(
+                    self.source,
+                    span.0,
+                    span.1,
+                ))
+            }),
+            ..e
+        }
+    }
+
+    /// Parse a program, consisting of a single unnamed term and named terms of
+    /// the form `(@term <name> <term>)`, along with declarations of the form
+    /// `(@cost <symbol> <cost>)`. The unnamed term is named `main`. Only the
+    /// `@term` and `@cost` symbols are reserved; other symbols starting with
+    /// `@` remain ordinary function symbols.
+    pub fn parse_program(
+        input: &str,
+        arity_checker: &mut ArityChecker,
+    ) -> Result<Vec<(String, Term)>, Vec<EggvizProgramParseError>> {
+        let mut tok = Parser::new(input);
+        let mut roots: Vec<(String, Term)> = Vec::new();
+        let mut unnamed_root = false;
+        loop {
+            let token = tok.consume();
+            let term_span = tok.token_span();
+            let root = match token {
+                Token::LParen => match tok.consume() {
+                    Token::ConcreteVariable(name) if name == COST_DECLARATION => {
+                        Self::parse_cost_declaration(&mut tok, arity_checker).map(|()| None)
+                    }
+                    Token::ConcreteVariable(name) if name == TERM_DECLARATION => {
+                        Self::parse_term_declaration(&mut tok, arity_checker)
+                            .map(|(name, name_span, term)| Some((Some((name, name_span)), term)))
+                    }
+                    token => {
+                        tok.push_back(token);
+                        Self::parse_function(&mut tok, true, arity_checker)
+                            .map(|(f, args)| Some((None, Term::Invocation(f, args))))
+                    }
+                },
+                Token::None => break,
+                token => {
+                    tok.push_back(token);
+                    Self::parse_term(&mut tok, true, arity_checker, false)
+                        .map(|term| term.map(|term| (None, term)))
+                }
+            };
+
+            match root {
+                Ok(None) => (),
+                Ok(Some((None, term))) => {
+                    if unnamed_root {
+                        let e = tok.error_at(
+                            term_span,
+                            format!(
+                                "Unexpected token at end of expression. Additional terms must be named with '({} <name> <term>)'.",
+                                TERM_DECLARATION
+                            ),
+                        );
+                        tok.errors.push(e);
+                    } else {
+                        unnamed_root = true;
+                        Self::add_root(&mut tok, &mut roots, DEFAULT_ROOT_NAME, term_span, term);
+                    }
+                }
+                Ok(Some((Some((name, name_span)), term))) => {
+                    Self::add_root(&mut tok, &mut roots, &name, name_span, term);
+                }
+                Err(e) => {
+                    // Skip the rest of the erroneous top-level term or
+                    // declaration and continue with the next one:
+                    tok.errors.push(e);
+                    if !tok.recover(0) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let roots = if !roots.is_empty() {
+            Ok(roots)
+        } else if !tok.errors.is_empty() {
+            // Don't complain about an empty program if it's empty because of
+            // errors already reported:
+            Err(tok.errors.pop().unwrap())
+        } else {
+            Err(tok.error("Empty expression."))
+        };
+        tok.finish(roots)
+    }
+
+    fn add_root(
+        tok: &mut Parser,
+        roots: &mut Vec<(String, Term)>,
+        name: &str,
+        name_span: Span,
+        term: Term,
+    ) {
+        if roots.iter().any(|(existing, _)| existing == name) {
+            let e = tok.error_at(name_span, format!("Term '{}' was already declared.", name));
+            tok.errors.push(e);
+        } else {
+            roots.push((name.to_string(), term));
+        }
+    }
+
+    fn parse_term_declaration(
+        tok: &mut Parser,
+        arity_checker: &mut ArityChecker,
+    ) -> Result<(String, Span, Term), EggvizProgramParseError> {
+        let name = match tok.consume() {
+            Token::GenericVariable(name) | Token::ConcreteVariable(name) => Ok(name),
+            _ => Err(tok.error("Expected name in term declaration.")),
+        }?;
+        let name_span = tok.token_span();
+        let term = Self::parse_term(tok, true, arity_checker, true)?
+            .ok_or_else(|| tok.error("Expected term in term declaration."))?;
+        if let Token::RParen = tok.consume() {
+            Ok((name, name_span, term))
+        } else {
+            Err(tok.error("Expected ')' at end of term declaration."))
+        }
+    }
+
+    fn parse_cost_declaration(
+        tok: &mut Parser,
+        arity_checker: &mut ArityChecker,
+    ) -> Result<(), EggvizProgramParseError> {
+        let name = match tok.consume() {
+            Token::GenericVariable(name) | Token::ConcreteVariable(name) => Ok(name),
+            _ => Err(tok.error("Expected function symbol in cost declaration.")),
+        }?;
+        let name_span = tok.token_span();
+        let cost = match tok.consume() {
+            Token::ConcreteVariable(cost) => cost
+                .parse::<f64>()
+                .ok()
+                .filter(|cost| *cost >= 0.0 && cost.is_finite())
+                .ok_or_else(|| {
+                    tok.error_owned(format!(
+                        "Invalid cost '{}' for symbol '{}'. Costs must be non-negative numbers.",
+                        cost, name
+                    ))
+                }),
+            _ => Err(tok.error("Expected cost in cost declaration.")),
+        }?;
+        if let Token::RParen = tok.consume() {
+            arity_checker
+                .declare_cost(&name, cost)
+                .map_err(|e| tok.locate(name_span, e))
+        } else {
+            Err(tok.error("Expected ')' at end of cost declaration."))
+        }
+    }
+
     pub fn parse(
         input: &str,
         disallow_generics: bool,
         arity_checker: &mut ArityChecker,
-    ) -> Result<Term, EggvizProgramParseError> {
-        let mut tok = Parser {
-            input: input.chars().peekable(),
-        };
-        match Self::parse_term(&mut tok, disallow_generics, arity_checker, false)? {
-            None => Err(EggvizProgramParseError::context_less("Empty expression.")),
-            Some(t) => {
+    ) -> Result<Term, Vec<EggvizProgramParseError>> {
+        let mut tok = Parser::new(input);
+        let term = match Self::parse_term(&mut tok, disallow_generics, arity_checker, false) {
+            Ok(None) => Err(tok.error("Empty expression.")),
+            Ok(Some(t)) => {
                 if let Token::None = tok.consume() {
                     Ok(t)
                 } else {
-                    Err(EggvizProgramParseError::context_less(
-                        "Unexpected token at end of expression.",
-                    ))
+                    Err(tok.error("Unexpected token at end of expression."))
                 }
             }
+            Err(e) => Err(e),
+        };
+        tok.finish(term)
+    }
+
+    /// Parse the right-hand side of a rewrite rule, consisting of a term
+    /// optionally followed by a side condition of the form `if <condition>`.
+    pub fn parse_rewrite_right(
+        input: &str,
+        arity_checker: &mut ArityChecker,
+    ) -> Result<(Term, Option<Condition>), Vec<EggvizProgramParseError>> {
+        let mut tok = Parser::new(input);
+        // Continue with the condition even if the term fails to parse, to
+        // report errors in both:
+        let term = match Self::parse_term(&mut tok, false, arity_checker, false) {
+            Ok(Some(term)) => Some(term),
+            Ok(None) => {
+                let e = tok.error("Empty expression.");
+                tok.errors.push(e);
+                None
+            }
+            Err(e) => {
+                tok.errors.push(e);
+                tok.recover(0);
+                None
+            }
+        };
+        let condition = match tok.consume() {
+            Token::None => Ok(None),
+            Token::ConcreteVariable(keyword) if keyword == CONDITION_IDENTIFIER => {
+                Self::parse_condition(&mut tok, arity_checker).and_then(|condition| {
+                    if let Token::None = tok.consume() {
+                        Ok(Some(condition))
+                    } else {
+                        Err(tok.error("Unexpected token at end of condition."))
+                    }
+                })
+            }
+            _ => Err(tok.error("Unexpected token at end of expression.")),
+        };
+        // A missing term has always been reported as an error:
+        tok.finish(condition.map(|condition| term.map(|term| (term, condition))))
+            .map(|right| right.expect("missing term without error"))
+    }
+
+    /// Parse a condition of the form `(== <term> <term>)`,
+    /// `(!= <term> <term>)`, `(not <condition>)`, `(and <condition>...)` or
+    /// `(or <condition>...)`.
+    fn parse_condition(
+        tok: &mut Parser,
+        arity_checker: &mut ArityChecker,
+    ) -> Result<Condition, EggvizProgramParseError> {
+        if !matches!(tok.consume(), Token::LParen) {
+            return Err(tok.error("Expected '(' at start of condition."));
+        }
+        let operator = match tok.consume() {
+            Token::GenericVariable(op) | Token::ConcreteVariable(op) => op,
+            _ => return Err(tok.error("Expected condition operator.")),
+        };
+        let operator_span = tok.token_span();
+        let condition = match operator.as_str() {
+            "==" | "!=" => {
+                let mut operands = Vec::new();
+                while let Some(term) = Self::parse_term(tok, false, arity_checker, true)? {
+                    operands.push(term);
+                }
+                if operands.len() != 2 {
+                    return Err(tok.error_at(operator_span, format!(
+                        "Condition '{}' expects 2 operands, but got {}.",
+                        operator,
+                        operands.len()
+                    )));
+                }
+                let right = operands.pop().unwrap();
+                let left = operands.pop().unwrap();
+                return Ok(if operator == "==" {
+                    Condition::Equal(left, right)
+                } else {
+                    Condition::NotEqual(left, right)
+                });
+            }
+            "not" => Condition::Not(Box::new(Self::parse_condition(tok, arity_checker)?)),
+            "and" | "or" => {
+                let mut conditions = Vec::new();
+                loop {
+                    match tok.consume() {
+                        Token::RParen => break,
+                        token => {
+                            tok.push_back(token);
+                            conditions.push(Self::parse_condition(tok, arity_checker)?);
+                        }
+                    }
+                }
+                if conditions.is_empty() {
+                    return Err(tok.error_at(operator_span, format!(
+                        "Condition '{}' expects at least one operand.",
+                        operator
+                    )));
+                }
+                return Ok(if operator == "and" {
+                    Condition::And(conditions)
+                } else {
+                    Condition::Or(conditions)
+                });
+            }
+            _ => {
+                return Err(tok.error_owned(format!(
+                    "Unknown condition operator '{}'. Expected one of '==', '!=', 'not', 'and' or 'or'.",
+                    operator
+                )))
+            }
+        };
+        if let Token::RParen = tok.consume() {
+            Ok(condition)
+        } else {
+            Err(tok.error("Expected ')' at end of condition."))
         }
     }
 
@@ -245,28 +839,28 @@ impl Parser<'_> {
             }
             Token::GenericVariable(v) => {
                 if disallow_generics {
-                    Err(EggvizProgramParseError::context_less_owned(
+                    Err(tok.error_owned(
                         format!("Unexpected generic variable in program. Variables beginning with '{}' are reserved for generic variables in rewrite rules.", GENERIC_IDENTIFIER.to_string())
                     ))
                 } else {
                     Ok(Some(Term::Singleton(Variable::Generic(v))))
                 }
             }
-            Token::ConcreteVariable(v) => Ok(Some(Term::Singleton(Variable::Concrete(v)))),
+            Token::ConcreteVariable(v) => match Variable::parse_integer(&v) {
+                Ok(Some(i)) => Ok(Some(Term::Singleton(Variable::Integer(i)))),
+                Ok(None) => Ok(Some(Term::Singleton(Variable::Concrete(v)))),
+                Err(e) => Err(tok.locate(tok.token_span(), e)),
+            },
             Token::RParen => {
                 if internal {
                     Ok(None)
                 } else {
-                    Err(EggvizProgramParseError::context_less(
-                        "Expected function term or variable name. Got ')'.",
-                    ))
+                    Err(tok.error("Expected function term or variable name. Got ')'."))
                 }
             }
             Token::None => {
                 if internal {
-                    Err(EggvizProgramParseError::context_less(
-                        "Unmatched '(' token.",
-                    ))
+                    Err(tok.error("Unmatched '(' token."))
                 } else {
                     Ok(None)
                 }
@@ -280,38 +874,60 @@ impl Parser<'_> {
         arity_checker: &mut ArityChecker,
     ) -> Result<(Function, Vec<Term>), EggvizProgramParseError> {
         let name = match tok.consume() {
-            Token::LParen => Err(EggvizProgramParseError::context_less(
-                "Cannot have two '(' tokens in a row.",
-            )),
+            Token::LParen => Err(tok.error("Cannot have two '(' tokens in a row.")),
             Token::GenericVariable(name) => {
                 // It's ok for functions to have generic names. They're functions.
                 // The distinction only matters for normal variables.
                 Ok(name)
             }
-            Token::ConcreteVariable(name) => Ok(name),
-            Token::RParen => Err(EggvizProgramParseError::context_less(
-                "Empty function body.",
-            )),
-            Token::None => Err(EggvizProgramParseError::context_less(
-                "Unexpected end of input.",
-            )),
+            Token::ConcreteVariable(name) => match Variable::parse_integer(&name) {
+                Ok(None) => Ok(name),
+                _ => Err(tok.error_owned(format!(
+                    "Integer literal '{}' cannot be used as a function name.",
+                    name
+                ))),
+            },
+            Token::RParen => Err(tok.error("Empty function body.")),
+            Token::None => Err(tok.error("Unexpected end of input.")),
         }?;
+        let name_span = tok.token_span();
         let mut arguments = Vec::new();
+        let mut erroneous_arguments = false;
         loop {
-            match Self::parse_term(tok, disallow_generics, arity_checker, true)? {
-                None => {
+            let depth = tok.depth;
+            match Self::parse_term(tok, disallow_generics, arity_checker, true) {
+                Ok(None) => {
                     let arity = arguments.len();
-                    arity_checker.check_new_arity(&name, arity)?;
+                    // The arity is unknown if an argument was skipped:
+                    if !erroneous_arguments {
+                        arity_checker
+                            .check_new_arity(&name, arity)
+                            .map_err(|e| tok.locate(name_span, e))?;
+                    }
                     return Ok((Function::new(name, arity), arguments));
                 }
-                Some(arg) => {
+                Ok(Some(arg)) => {
                     arguments.push(arg);
                 }
+                Err(e) => {
+                    // Skip the rest of the erroneous argument and continue
+                    // with the next one:
+                    if !tok.recover(depth) {
+                        return Err(e);
+                    }
+                    tok.errors.push(e);
+                    erroneous_arguments = true;
+                }
             }
         }
     }
 
     fn consume(&mut self) -> Token {
+        if let Some((token, span)) = self.pushback.take() {
+            self.token_span = span;
+            return token;
+        }
+
         let mut token = String::new();
         let make_variable_token = |tok: String| {
             if tok.starts_with(GENERIC_IDENTIFIER) {
@@ -320,44 +936,58 @@ impl Parser<'_> {
                 Token::ConcreteVariable(tok)
             }
         };
-        loop {
+        let token = loop {
+            if token.is_empty() {
+                self.token_span.0 = self.offset;
+            }
             match self.input.peek() {
                 None => {
-                    return if token.is_empty() {
+                    self.exhausted = true;
+                    break if token.is_empty() {
                         Token::None
                     } else {
                         make_variable_token(token)
-                    }
+                    };
                 }
                 Some(&c) => {
                     if c.is_whitespace() {
-                        self.input.next();
                         if token.is_empty() {
+                            self.advance();
                             continue;
                         } else {
-                            return make_variable_token(token);
+                            break make_variable_token(token);
                         }
                     }
                     if c == '(' {
-                        return if token.is_empty() {
-                            self.input.next();
+                        break if token.is_empty() {
+                            self.advance();
+                            self.depth += 1;
                             Token::LParen
                         } else {
                             make_variable_token(token)
                         };
                     } else if c == ')' {
-                        return if token.is_empty() {
-                            self.input.next();
+                        break if token.is_empty() {
+                            self.advance();
+                            self.depth = self.depth.saturating_sub(1);
                             Token::RParen
                         } else {
                             make_variable_token(token)
                         };
                     } else {
                         token.push(c);
-                        self.input.next();
+                        self.advance();
                     }
                 }
             };
+        };
+        self.token_span.1 = self.offset - self.token_span.0;
+        token
+    }
+
+    fn advance(&mut self) {
+        if let Some(c) = self.input.next() {
+            self.offset += c.len_utf8();
         }
     }
 }
@@ -369,3 +999,180 @@ pub enum Token {
     RParen,
     None,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_program(input: &str) -> (ArityChecker, Vec<EggvizProgramParseError>) {
+        let mut arity_checker = ArityChecker::default();
+        let mut diagnostics = Vec::new();
+        Program::parse_str(input, &mut arity_checker, &mut diagnostics);
+        (arity_checker, diagnostics)
+    }
+
+    fn position(e: &EggvizProgramParseError) -> (usize, usize) {
+        match e.context {
+            Some(EggvizProgramParseContext::Program { line, column, .. })
+            | Some(EggvizProgramParseContext::RewriteRule { line, column, .. }) => (line, column),
+            None => panic!("Diagnostic without a context: {}", e),
+        }
+    }
+
+    #[test]
+    fn program_reports_all_errors() {
+        let (_, diagnostics) = parse_program("(@cost f a)\n(f (g x) (g x y))");
+        assert!(diagnostics.iter().all(|e| e.is_error()));
+        assert_eq!(
+            diagnostics.iter().map(position).collect::<Vec<_>>(),
+            vec![(1, 10), (2, 11)]
+        );
+    }
+
+    #[test]
+    fn program_recovers_after_unexpected_terms() {
+        let (_, diagnostics) = parse_program("(+ (f x) (f x y))\n  (g z) (g z w)");
+        assert_eq!(
+            diagnostics.iter().map(position).collect::<Vec<_>>(),
+            vec![(1, 11), (2, 3), (2, 10)]
+        );
+    }
+
+    #[test]
+    fn rewrite_rule_reports_errors_on_both_sides() {
+        let (mut arity_checker, diagnostics) = parse_program("(f x y)");
+        assert!(diagnostics.is_empty());
+
+        let label = EggvizRewriteRuleLabel::Supplied("arity".to_string());
+        let mut diagnostics = Vec::new();
+        let rule = Program::parse_rewrite_rule(
+            &mut arity_checker,
+            &label,
+            "(f pa pb pc)",
+            "(f pa)",
+            &mut diagnostics,
+        );
+        assert!(rule.is_none());
+
+        let sides: Vec<_> = diagnostics
+            .iter()
+            .map(|e| match e.context {
+                Some(EggvizProgramParseContext::RewriteRule {
+                    label: ref rule_label,
+                    side,
+                    ..
+                }) if *rule_label == label => side,
+                _ => panic!("Diagnostic not attributed to the rule: {}", e),
+            })
+            .collect();
+        assert_eq!(
+            sides,
+            vec![EggvizRewriteRuleSide::Left, EggvizRewriteRuleSide::Right]
+        );
+    }
+
+    #[test]
+    fn position_after_newline() {
+        let (_, diagnostics) = parse_program("(f x y)\n(f x)");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(position(&diagnostics[0]), (2, 2));
+
+        // Columns are counted in characters, not bytes:
+        let source = "x\n\u{e9}\u{e9} y";
+        match EggvizProgramParseContext::program(source, source.find('y').unwrap(), 1) {
+            EggvizProgramParseContext::Program {
+                offset,
+                line,
+                column,
+                ..
+            } => assert_eq!((offset, line, column), (7, 2, 4)),
+            context => panic!("Unexpected context: {:?}", context),
+        }
+    }
+
+    #[test]
+    fn only_cost_declarations_are_reserved() {
+        let (mut arity_checker, diagnostics) = parse_program("(@cost f 2)\n(@foo (f x))");
+        assert!(diagnostics.is_empty());
+        assert!(arity_checker.check_new_arity("@foo", 1).is_ok());
+        assert!(arity_checker.check_new_arity("@foo", 2).is_err());
+    }
+
+    #[test]
+    fn named_root_terms() {
+        let mut arity_checker = ArityChecker::default();
+        let roots = Parser::parse_program("(@term lhs (f x))\n(g x)", &mut arity_checker)
+            .unwrap_or_else(|_| panic!("Program should parse"));
+        assert_eq!(
+            roots
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["lhs", "main"]
+        );
+
+        let (_, diagnostics) = parse_program("(@term t x)\n(@term t y)");
+        assert_eq!(
+            diagnostics.iter().map(position).collect::<Vec<_>>(),
+            [(2, 8)]
+        );
+    }
+
+    #[test]
+    fn fold_checks_overflow() {
+        assert_eq!(ConstantFolding::fold(ADDITION, &[2, 3, 4]), Some(9));
+        assert_eq!(ConstantFolding::fold(MULTIPLICATION, &[2, -3]), Some(-6));
+        assert_eq!(ConstantFolding::fold(SUBTRACTION, &[2, 3, 4]), Some(-5));
+        assert_eq!(ConstantFolding::fold(ADDITION, &[i64::MAX, 1]), None);
+        assert_eq!(ConstantFolding::fold(MULTIPLICATION, &[i64::MIN, -1]), None);
+        assert_eq!(ConstantFolding::fold(SUBTRACTION, &[i64::MIN, 1]), None);
+        assert_eq!(ConstantFolding::fold("f", &[1, 2]), None);
+    }
+
+    #[test]
+    fn fold_unary_minus() {
+        assert_eq!(ConstantFolding::fold(SUBTRACTION, &[5]), Some(-5));
+        assert_eq!(ConstantFolding::fold(SUBTRACTION, &[i64::MIN]), None);
+        assert_eq!(ConstantFolding::fold(ADDITION, &[5]), Some(5));
+        assert_eq!(ConstantFolding::fold(SUBTRACTION, &[]), None);
+    }
+
+    #[test]
+    fn parse_integer_literals() {
+        assert_eq!(Variable::parse_integer("+5").unwrap(), Some(5));
+        assert_eq!(Variable::parse_integer("-5").unwrap(), Some(-5));
+        assert_eq!(Variable::parse_integer("+").unwrap(), None);
+        assert_eq!(Variable::parse_integer("5a").unwrap(), None);
+        assert!(Variable::parse_integer("9223372036854775808").is_err());
+
+        // Literals are normalized, such that `+5` and `5` are the same node:
+        let term = Parser::parse("(f +5 -0)", true, &mut ArityChecker::default())
+            .unwrap_or_else(|_| panic!("Term should parse"));
+        assert_eq!(term.to_egg(), "(f 5 0)");
+    }
+
+    #[test]
+    fn merge_marks_conflicts() {
+        let mut analysis = ConstantFolding;
+        let mut data = Some(Constant::Value(1));
+        let did_merge = egg::Analysis::<Language>::merge(&mut analysis, &mut data, None);
+        assert_eq!((did_merge.0, did_merge.1), (false, true));
+        let did_merge =
+            egg::Analysis::<Language>::merge(&mut analysis, &mut data, Some(Constant::Value(1)));
+        assert_eq!((did_merge.0, did_merge.1), (false, false));
+
+        let did_merge =
+            egg::Analysis::<Language>::merge(&mut analysis, &mut data, Some(Constant::Value(2)));
+        assert_eq!((did_merge.0, did_merge.1), (true, true));
+        assert_eq!(data, Some(Constant::Conflict));
+        assert_eq!(
+            Program::analysis_data_to_string(&data),
+            "= conflicting constants"
+        );
+
+        let did_merge =
+            egg::Analysis::<Language>::merge(&mut analysis, &mut data, Some(Constant::Value(3)));
+        assert_eq!((did_merge.0, did_merge.1), (false, true));
+        assert_eq!(data, Some(Constant::Conflict));
+    }
+}
@@ -0,0 +1,395 @@
+//! Scripted sessions of an [`EggvizRuntime`], for checking that examples keep
+//! working as expected.
+//!
+//! A script consists of one command per line. Empty lines and lines starting
+//! with `#` are ignored:
+//!
+//! ```text
+//! # The program and rewrite rules must precede all other commands:
+//! program (+ (* x 2) y)
+//! rule double: (* pa 2) => (<< pa 1)
+//! rule (+ pa pb) <=> (+ pb pa)
+//!
+//! apply double
+//! auto 2
+//! assert-equivalent (+ (* x 2) y) (+ y (<< x 1))
+//! ```
+//!
+//! The following commands are supported:
+//!
+//! - `program <text>`: append a line to the program.
+//! - `rule [name:] <lhs> <direction> <rhs>`: add a rewrite rule, where the
+//!   direction is `=>` or `<=>`. Unnamed rules are labeled by their index.
+//! - `apply <label>`: apply a single rewrite rule, given by its label or name.
+//! - `auto [n]`: apply all rewrite rules once, `n` times (default: 1).
+//! - `saturate`: apply all rewrite rules until the e-graph is saturated. Fails
+//!   if any of the default [`EggvizRunLimits`] is reached first.
+//! - `union <a> <b>`: merge two e-classes, given by their ids.
+//! - `add <term>`: add a term to the e-graph.
+//! - `assert-equivalent <term> <term>`: check that both terms are represented
+//!   in the e-graph, in the same e-class.
+//! - `assert-not-equivalent <term> <term>`: check that the terms are not in
+//!   the same e-class.
+//! - `assert-classes <n>`: check the number of e-classes in the e-graph.
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::EggvizProgram;
+use crate::EggvizRewriteDirection;
+use crate::EggvizRewriteRuleLabel;
+use crate::EggvizRunLimits;
+use crate::EggvizRuntime;
+
+/// File extension of scripts run by [`run_script_dir`].
+pub const SCRIPT_EXTENSION: &str = "eggviz";
+
+/// Optional name, left-hand side, direction and right-hand side of a rewrite
+/// rule, as accepted by [`EggvizRuntime::new`].
+pub type EggvizScriptRule = (Option<String>, String, EggvizRewriteDirection, String);
+
+/// Trimmed lines of a script or rules file along with their (1-based) line
+/// numbers, skipping empty lines and comments starting with `#`.
+pub fn significant_lines(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Parse the label of a rewrite rule (`rwr:name`, `rwr#N`, ...). Any other
+/// string is taken to be the name of a named rule.
+pub fn parse_rule_label(label: &str) -> EggvizRewriteRuleLabel {
+    EggvizRewriteRuleLabel::from_str(label)
+        .unwrap_or_else(|()| EggvizRewriteRuleLabel::Supplied(label.to_string()))
+}
+
+/// Parse a rewrite rule of the form `[name:] <lhs> <direction> <rhs>`.
+pub fn parse_rewrite_rule(rule: &str) -> Result<EggvizScriptRule, String> {
+    // A name is only split off if it precedes the first term:
+    let (name, rule) = match rule.split_once(':') {
+        Some((name, rule))
+            if !name.trim().is_empty()
+                && !name
+                    .trim()
+                    .contains(|c: char| c.is_whitespace() || c == '(') =>
+        {
+            (Some(name.trim().to_string()), rule)
+        }
+        _ => (None, rule),
+    };
+
+    let mut tokens = rule.split_whitespace();
+    let mut left = Vec::new();
+    let direction = loop {
+        match tokens.next() {
+            Some(token) => match EggvizRewriteDirection::from_str(token) {
+                Ok(direction) => break direction,
+                Err(()) => left.push(token),
+            },
+            None => return Err("Rewrite rule is missing a direction (=> or <=>)".to_string()),
+        }
+    };
+    let right: Vec<&str> = tokens.collect();
+
+    Ok((name, left.join(" "), direction, right.join(" ")))
+}
+
+/// Split a string into its top-level terms, i.e. parenthesized expressions
+/// and symbols. Returns [`None`] if the parentheses are unbalanced.
+fn split_terms(s: &str) -> Option<Vec<&str>> {
+    let mut terms = Vec::new();
+    let mut start = None;
+    let mut depth = 0_usize;
+    for (idx, c) in s.char_indices() {
+        match c {
+            '(' => {
+                if depth == 0 {
+                    if let Some(start) = start.take() {
+                        terms.push(&s[start..idx]);
+                    }
+                    start = Some(idx);
+                }
+                depth += 1;
+            }
+            ')' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    terms.push(&s[start.take()?..idx + 1]);
+                }
+            }
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(start) = start.take() {
+                    terms.push(&s[start..idx]);
+                }
+            }
+            _ => {
+                if start.is_none() {
+                    start = Some(idx);
+                }
+            }
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+    if let Some(start) = start {
+        terms.push(&s[start..]);
+    }
+    Some(terms)
+}
+
+/// Single command of an [`EggvizScript`].
+#[derive(Clone, Debug)]
+pub enum EggvizScriptCommand {
+    Program(String),
+    Rule(EggvizScriptRule),
+    Apply(EggvizRewriteRuleLabel),
+    Auto(NonZeroUsize),
+    Saturate,
+    Union(egg::Id, egg::Id),
+    Add(String),
+    /// Assert that two terms are equivalent (or, if `false`, that they are
+    /// not).
+    AssertEquivalent(String, String, bool),
+    AssertClasses(usize),
+}
+
+impl FromStr for EggvizScriptCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (command, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = args.trim();
+        let number = |arg: &str| {
+            arg.parse::<usize>()
+                .map_err(|_| format!("Expected a number, got \"{}\"", arg))
+        };
+        let terms = |count: usize| match split_terms(args) {
+            Some(terms) if terms.len() == count => Ok(terms),
+            _ => Err(format!("Expected {} term(s), got \"{}\"", count, args)),
+        };
+
+        match command {
+            "program" => Ok(EggvizScriptCommand::Program(args.to_string())),
+            "rule" => parse_rewrite_rule(args).map(EggvizScriptCommand::Rule),
+            "apply" => Ok(EggvizScriptCommand::Apply(parse_rule_label(args))),
+            "auto" if args.is_empty() => {
+                Ok(EggvizScriptCommand::Auto(NonZeroUsize::new(1).unwrap()))
+            }
+            "auto" => NonZeroUsize::new(number(args)?)
+                .map(EggvizScriptCommand::Auto)
+                .ok_or_else(|| "Expected at least one step".to_string()),
+            "saturate" => Ok(EggvizScriptCommand::Saturate),
+            "union" => match args.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [a, b] => Ok(EggvizScriptCommand::Union(
+                    egg::Id::from(number(a)?),
+                    egg::Id::from(number(b)?),
+                )),
+                _ => Err(format!("Expected two e-class ids, got \"{}\"", args)),
+            },
+            "add" => terms(1).map(|_| EggvizScriptCommand::Add(args.to_string())),
+            "assert-equivalent" | "assert-not-equivalent" => terms(2).map(|terms| {
+                EggvizScriptCommand::AssertEquivalent(
+                    terms[0].to_string(),
+                    terms[1].to_string(),
+                    command == "assert-equivalent",
+                )
+            }),
+            "assert-classes" => number(args).map(EggvizScriptCommand::AssertClasses),
+            _ => Err(format!("Unknown command \"{}\"", command)),
+        }
+    }
+}
+
+/// Failure of an [`EggvizScript`], at the given (1-based) step and line.
+///
+/// Each command of the script is a step, such that failures in parsing the
+/// program or rewrite rules are reported at the first step following them.
+#[derive(Clone, Debug)]
+pub struct EggvizScriptError {
+    pub step: usize,
+    pub line: usize,
+    pub msg: String,
+}
+
+impl Display for EggvizScriptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "step {} (line {}): {}", self.step, self.line, self.msg)
+    }
+}
+
+/// Script of commands to be run on an [`EggvizRuntime`].
+#[derive(Clone, Debug)]
+pub struct EggvizScript {
+    /// Commands, along with the (1-based) line they are on.
+    pub commands: Vec<(usize, EggvizScriptCommand)>,
+}
+
+impl FromStr for EggvizScript {
+    type Err = EggvizScriptError;
+
+    fn from_str(script: &str) -> Result<Self, Self::Err> {
+        let commands = significant_lines(script)
+            .enumerate()
+            .map(|(step, (line, command))| {
+                command
+                    .parse()
+                    .map(|command| (line, command))
+                    .map_err(|msg| EggvizScriptError {
+                        step: step + 1,
+                        line,
+                        msg,
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(EggvizScript { commands })
+    }
+}
+
+impl EggvizScript {
+    /// Run the script on a fresh runtime, which is constructed from the
+    /// script's program and rewrite rules once the first other command is
+    /// reached. Stops at the first failing command.
+    pub fn run<P: EggvizProgram>(&self) -> Result<EggvizRuntime<P>, EggvizScriptError>
+    where
+        <P::Analysis as egg::Analysis<P::Language>>::Data: Clone,
+    {
+        // The program and rewrite rules are collected up to the first other
+        // command:
+        let mut program = Vec::new();
+        let mut rules = Vec::new();
+        for (_, command) in self.commands.iter() {
+            match command {
+                EggvizScriptCommand::Program(text) => program.push(text.as_str()),
+                EggvizScriptCommand::Rule(rule) => rules.push(rule.clone()),
+                _ => break,
+            }
+        }
+        let prelude = program.len() + rules.len();
+
+        // Failures of the program or rewrite rules are reported at the first
+        // step following them, or the last step if there is none:
+        let (step, line) = match self.commands.get(prelude) {
+            Some((line, _)) => (prelude + 1, *line),
+            None => (
+                self.commands.len(),
+                self.commands.last().map_or(0, |(line, _)| *line),
+            ),
+        };
+        let mut runtime =
+            EggvizRuntime::new(program.join("\n"), rules).map_err(|e| EggvizScriptError {
+                step,
+                line,
+                msg: e.to_string(),
+            })?;
+
+        for (step, (line, command)) in self.commands.iter().enumerate().skip(prelude) {
+            let error = |msg: String| EggvizScriptError {
+                step: step + 1,
+                line: *line,
+                msg,
+            };
+
+            match command {
+                EggvizScriptCommand::Program(_) | EggvizScriptCommand::Rule(_) => {
+                    return Err(error(
+                        "The program and rewrite rules must precede all other commands".to_string(),
+                    ));
+                }
+                EggvizScriptCommand::Apply(rule) => {
+                    runtime
+                        .preview_rule(rule)
+                        .map_err(|e| error(e.to_string()))?;
+                    runtime.rewrite_rule(rule.clone());
+                }
+                EggvizScriptCommand::Auto(steps) => {
+                    for _ in 0..steps.get() {
+                        runtime.rewrite_auto();
+                    }
+                }
+                EggvizScriptCommand::Saturate => {
+                    let report = runtime.run_until_saturated(&EggvizRunLimits::default());
+                    if !matches!(report.stop_reason, egg::StopReason::Saturated) {
+                        return Err(error(format!(
+                            "Expected saturation, but stopped after {} iterations: {:?}",
+                            report.iterations.len(),
+                            report.stop_reason
+                        )));
+                    }
+                }
+                EggvizScriptCommand::Union(a, b) => {
+                    runtime.union(*a, *b).map_err(|e| error(e.to_string()))?;
+                }
+                EggvizScriptCommand::Add(term) => {
+                    runtime.add_term(term).map_err(|e| error(e.to_string()))?;
+                }
+                EggvizScriptCommand::AssertEquivalent(lhs, rhs, expected) => {
+                    let equivalence = runtime
+                        .are_equivalent(lhs, rhs)
+                        .map_err(|e| error(e.to_string()))?;
+                    for (term, eclass) in [(lhs, equivalence.lhs), (rhs, equivalence.rhs)] {
+                        if *expected && eclass.is_none() {
+                            return Err(error(format!(
+                                "Term {} is not represented in the e-graph",
+                                term
+                            )));
+                        }
+                    }
+                    if equivalence.equivalent() != *expected {
+                        return Err(error(format!(
+                            "Expected terms {} and {} {}to be equivalent",
+                            lhs,
+                            rhs,
+                            if *expected { "" } else { "not " }
+                        )));
+                    }
+                }
+                EggvizScriptCommand::AssertClasses(expected) => {
+                    let classes = runtime.number_of_classes();
+                    if classes != *expected {
+                        return Err(error(format!(
+                            "Expected {} e-classes, but the e-graph has {}",
+                            expected, classes
+                        )));
+                    }
+                }
+            }
+        }
+
+        Ok(runtime)
+    }
+}
+
+/// Run every script (with the [`SCRIPT_EXTENSION`]) in a directory, in order
+/// of their file names, returning the result of each of them.
+pub fn run_script_dir<P: EggvizProgram>(
+    dir: impl AsRef<Path>,
+) -> std::io::Result<Vec<(PathBuf, Result<(), EggvizScriptError>)>>
+where
+    <P::Analysis as egg::Analysis<P::Language>>::Data: Clone,
+{
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|path| {
+        path.extension()
+            .map_or(false, |ext| ext == SCRIPT_EXTENSION)
+    });
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let script = std::fs::read_to_string(&path)?;
+            let result = script
+                .parse::<EggvizScript>()
+                .and_then(|script| script.run::<P>().map(|_| ()));
+            Ok((path, result))
+        })
+        .collect()
+}
@@ -0,0 +1,975 @@
+//! Bindings exposing an [`EggvizRuntime`] parametrized over the lispylang to
+//! JS, enabled through the `wasm` feature.
+
+use std::collections::LinkedList;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+use web_sys::console;
+
+use crate::lispylang;
+use crate::EggvizCostFunction;
+use crate::EggvizExplanation;
+use crate::EggvizExplanationRule;
+use crate::EggvizExplanationTree;
+use crate::EggvizProgramParseContext;
+use crate::EggvizProgramParseError;
+use crate::EggvizRewriteDirection;
+use crate::EggvizRewriteRuleLabel;
+use crate::EggvizRuleMatch;
+use crate::EggvizRunLimits;
+use crate::EggvizRuntime;
+use crate::EggvizRuntimeError;
+use crate::EggvizSession;
+use crate::EggvizSessionStep;
+use crate::EggvizStepReport;
+
+#[wasm_bindgen]
+pub struct LispylangEggvizRuntime {
+    inner: EggvizRuntime<lispylang::Program>,
+}
+
+#[wasm_bindgen]
+impl LispylangEggvizRuntime {
+    /// Construct a runtime from a program and an array of rewrite rule
+    /// objects of the form `{name, lhs, rhs, direction}`. The `name` is
+    /// optional; unnamed rules are labeled by their index (`rwr#N`), named
+    /// ones by their name (`rwr:name`). The optional `direction` is either
+    /// `=>` (or `→`, the default) or `<=>` (or `↔`).
+    ///
+    /// Errors are reported as a map with a `message`. Parse errors further
+    /// carry a list of all `diagnostics`, each with a `severity`, `message`
+    /// and, where known, the source position of the offending token: its
+    /// `context` (`program` or `rewrite-rule`, with the rule's `label` and
+    /// `side`), its byte `offset` and `length`, and its `line` and `column`.
+    pub fn new(
+        program_str: &str,
+        rewrite_rules_js: Box<[JsValue]>,
+    ) -> Result<LispylangEggvizRuntime, js_sys::Map> {
+        let rewrite_rules = rewrite_rules_js
+            .iter()
+            .enumerate()
+            .map(|(idx, rule)| rewrite_rule_from_js(idx, rule))
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(error_message_to_js)?;
+        Ok(LispylangEggvizRuntime {
+            inner: EggvizRuntime::new(program_str, rewrite_rules)
+                .map_err(|e| runtime_error_to_js(&e))?,
+        })
+    }
+
+    /// Map from the names of the program's root terms to their current
+    /// e-class ids, in program order.
+    pub fn roots(&self) -> js_sys::Map {
+        let roots = js_sys::Map::new();
+        for (name, eclass) in self.inner.roots() {
+            roots.set(
+                &js_sys::JsString::from(name),
+                &js_sys::JsString::from(eclass.to_string()),
+            );
+        }
+        roots
+    }
+
+    /// Warnings reported while parsing the program and its rewrite rules, in
+    /// the same format as the `diagnostics` of a constructor error.
+    pub fn diagnostics(&self) -> js_sys::Array {
+        self.inner
+            .diagnostics()
+            .iter()
+            .map(diagnostic_to_js)
+            .collect()
+    }
+
+    /// Serialize the current session into a JSON string: the program, the
+    /// rewrite rules (as passed to the constructor) and the steps which led to
+    /// the current e-graph. Steps which were undone are not included.
+    pub fn export_session(&self) -> Result<String, String> {
+        js_sys::JSON::stringify(&session_to_js(&self.inner.session()))
+            .map(String::from)
+            .map_err(|_| "Unable to serialize the session".to_string())
+    }
+
+    /// Restore a session exported through `export_session`, by replaying its
+    /// steps on a freshly constructed runtime and checking that they have the
+    /// same effect. Errors are reported in the same format as for the
+    /// constructor.
+    pub fn import_session(session_json: &str) -> Result<LispylangEggvizRuntime, js_sys::Map> {
+        let session = js_sys::JSON::parse(session_json)
+            .map_err(|_| "Unable to parse the session: invalid JSON".to_string())
+            .and_then(|session| session_from_js(&session))
+            .map_err(error_message_to_js)?;
+        Ok(LispylangEggvizRuntime {
+            inner: EggvizRuntime::from_session(&session).map_err(|e| runtime_error_to_js(&e))?,
+        })
+    }
+
+    pub fn rewrite_rule(&mut self, rule_label: &str) -> Result<js_sys::Map, String> {
+        let parsed_label = EggvizRewriteRuleLabel::from_str(rule_label)
+            .map_err(|_| format!("Unable to parse rule label \"{}\"", rule_label))?;
+        Ok(step_report_to_js(self.inner.rewrite_rule(parsed_label)))
+    }
+
+    pub fn rewrite_auto(&mut self) -> Result<js_sys::Map, String> {
+        Ok(step_report_to_js(self.inner.rewrite_auto()))
+    }
+
+    /// Manually merge two e-classes, given by their ids, and restore
+    /// congruence. Recorded in the history as applying the `union`
+    /// pseudo-rule.
+    pub fn union(&mut self, eclass_a: &str, eclass_b: &str) -> Result<js_sys::Map, String> {
+        let a = parse_eclass_id(eclass_a)?;
+        let b = parse_eclass_id(eclass_b)?;
+        self.inner
+            .union(a, b)
+            .map(step_report_to_js)
+            .map_err(|e| format!("{}", e))
+    }
+
+    /// Apply all rewrite rules until the e-graph is saturated, or any of the
+    /// passed limits is reached. The time limit is given in milliseconds.
+    pub fn run_until_saturated(
+        &mut self,
+        iter_limit: usize,
+        node_limit: usize,
+        time_limit_ms: f64,
+    ) -> Result<js_sys::Map, String> {
+        if !(time_limit_ms.is_finite() && time_limit_ms >= 0.0) {
+            return Err(format!("Invalid time limit {}ms", time_limit_ms));
+        }
+        let limits = EggvizRunLimits {
+            iter_limit: NonZeroUsize::new(iter_limit)
+                .ok_or_else(|| "Iteration limit must be at least 1".to_string())?,
+            node_limit,
+            time_limit: Duration::from_secs_f64(time_limit_ms / 1000.0),
+        };
+        let report = self.inner.run_until_saturated(&limits);
+
+        let (stop_reason, stop_value) = match report.stop_reason {
+            egg::StopReason::Saturated => ("saturated", None),
+            egg::StopReason::IterationLimit(iters) => ("iteration_limit", Some(iters as f64)),
+            egg::StopReason::NodeLimit(nodes) => ("node_limit", Some(nodes as f64)),
+            egg::StopReason::TimeLimit(secs) => ("time_limit", Some(secs * 1000.0)),
+            egg::StopReason::Other(_) => ("other", None),
+        };
+
+        let iterations: js_sys::Array = report
+            .iterations
+            .into_iter()
+            .map(|iteration| {
+                let applied = js_sys::Map::new();
+                for (rule, count) in iteration.applied.into_iter() {
+                    applied.set(
+                        &js_sys::JsString::from(rule.to_string()),
+                        &js_sys::Number::from(count as u32),
+                    );
+                }
+
+                let js_iteration = js_sys::Map::new();
+                js_iteration.set(
+                    &js_sys::JsString::from("egraph_nodes"),
+                    &js_sys::Number::from(iteration.egraph_nodes as u32),
+                );
+                js_iteration.set(
+                    &js_sys::JsString::from("egraph_classes"),
+                    &js_sys::Number::from(iteration.egraph_classes as u32),
+                );
+                js_iteration.set(&js_sys::JsString::from("applied"), &applied);
+                js_iteration.set(
+                    &js_sys::JsString::from("search_time_ms"),
+                    &js_sys::Number::from(iteration.search_time * 1000.0),
+                );
+                js_iteration.set(
+                    &js_sys::JsString::from("apply_time_ms"),
+                    &js_sys::Number::from(iteration.apply_time * 1000.0),
+                );
+                js_iteration.set(
+                    &js_sys::JsString::from("rebuild_time_ms"),
+                    &js_sys::Number::from(iteration.rebuild_time * 1000.0),
+                );
+                js_iteration.set(
+                    &js_sys::JsString::from("total_time_ms"),
+                    &js_sys::Number::from(iteration.total_time * 1000.0),
+                );
+                js_iteration
+            })
+            .collect();
+
+        let js_report = js_sys::Map::new();
+        js_report.set(
+            &js_sys::JsString::from("stop_reason"),
+            &js_sys::JsString::from(stop_reason),
+        );
+        if let Some(stop_value) = stop_value {
+            js_report.set(
+                &js_sys::JsString::from("stop_value"),
+                &js_sys::Number::from(stop_value),
+            );
+        }
+        js_report.set(&js_sys::JsString::from("iterations"), &iterations);
+        js_report.set(
+            &js_sys::JsString::from("step"),
+            &step_report_to_js(report.step),
+        );
+        Ok(js_report)
+    }
+
+    pub fn preview_rule(&self, rule_label: &str) -> Result<js_sys::Array, String> {
+        let parsed_label = EggvizRewriteRuleLabel::from_str(rule_label)
+            .map_err(|_| format!("Unable to parse rule label \"{}\"", rule_label))?;
+        Ok(self
+            .inner
+            .preview_rule(&parsed_label)
+            .map_err(|e| format!("{}", e))?
+            .into_iter()
+            .map(rule_match_to_js)
+            .collect())
+    }
+
+    /// Apply a rewrite rule only at its match with the given index, as
+    /// returned by `preview_rule`.
+    pub fn rewrite_rule_match(
+        &mut self,
+        rule_label: &str,
+        match_index: usize,
+    ) -> Result<js_sys::Map, String> {
+        let parsed_label = EggvizRewriteRuleLabel::from_str(rule_label)
+            .map_err(|_| format!("Unable to parse rule label \"{}\"", rule_label))?;
+        let rule_match = self
+            .inner
+            .preview_rule(&parsed_label)
+            .map_err(|e| format!("{}", e))?
+            .into_iter()
+            .nth(match_index)
+            .ok_or_else(|| {
+                format!(
+                    "Rewrite rule \"{}\" has no match with index {}",
+                    rule_label, match_index
+                )
+            })?;
+        self.inner
+            .rewrite_rule_match(rule_match)
+            .map(step_report_to_js)
+            .map_err(|e| format!("{}", e))
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.inner.undo()
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.inner.redo()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.inner.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.inner.can_redo()
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.inner.history_len()
+    }
+
+    /// Statistics of each rewrite rule, accumulated over all steps. Times are
+    /// given in milliseconds.
+    pub fn rule_stats(&self) -> js_sys::Array {
+        self.inner
+            .rule_stats()
+            .into_iter()
+            .map(|(rule, stats)| {
+                let js_stats = js_sys::Map::new();
+                js_stats.set(
+                    &js_sys::JsString::from("rule"),
+                    &js_sys::JsString::from(rule.to_string()),
+                );
+                for (name, value) in [
+                    ("searches", stats.searches as f64),
+                    ("matches", stats.matches as f64),
+                    ("applications", stats.applications as f64),
+                    ("nodes_added", stats.nodes_added as f64),
+                    ("classes_added", stats.classes_added as f64),
+                    ("search_time_ms", stats.search_time * 1000.0),
+                    ("apply_time_ms", stats.apply_time * 1000.0),
+                ] {
+                    js_stats.set(&js_sys::JsString::from(name), &js_sys::Number::from(value));
+                }
+                js_stats
+            })
+            .collect()
+    }
+
+    /// Add a term to the current e-graph, returning its e-class id.
+    pub fn add_term(&mut self, term: &str) -> Result<String, String> {
+        self.inner
+            .add_term(term)
+            .map(|id| id.to_string())
+            .map_err(|e| format!("{}", e))
+    }
+
+    /// Check whether two terms are equivalent in the current e-graph. Returns
+    /// a map with the e-class ids of both terms (`lhs` and `rhs`, undefined
+    /// if not represented) and whether they are `equivalent`.
+    pub fn are_equivalent(&self, lhs: &str, rhs: &str) -> Result<js_sys::Map, String> {
+        let equivalence = self
+            .inner
+            .are_equivalent(lhs, rhs)
+            .map_err(|e| format!("{}", e))?;
+
+        let js_equivalence = js_sys::Map::new();
+        for (key, eclass) in [("lhs", equivalence.lhs), ("rhs", equivalence.rhs)] {
+            js_equivalence.set(
+                &js_sys::JsString::from(key),
+                &eclass.map_or(JsValue::UNDEFINED, |id| {
+                    js_sys::JsString::from(id.to_string()).into()
+                }),
+            );
+        }
+        js_equivalence.set(
+            &js_sys::JsString::from("equivalent"),
+            &js_sys::Boolean::from(equivalence.equivalent()),
+        );
+        Ok(js_equivalence)
+    }
+
+    pub fn explain_equivalence(&self, lhs: &str, rhs: &str) -> Result<js_sys::Map, String> {
+        let explanation = self
+            .inner
+            .explain_equivalence(lhs, rhs)
+            .map_err(|e| format!("{}", e))?;
+        Ok(explanation_to_js(explanation))
+    }
+
+    /// Extract the best term of an e-class (or the program's first root term,
+    /// if none is given) according to a built-in cost function, either
+    /// `ast-size` or `ast-depth`, or the costs declared in the program
+    /// (`program`).
+    pub fn extract(
+        &self,
+        cost_function: &str,
+        eclass_id: Option<String>,
+    ) -> Result<js_sys::Map, String> {
+        let mut cost_function: Box<dyn EggvizCostFunction<lispylang::Language>> =
+            match cost_function {
+                "ast-size" => Box::new(egg::AstSize),
+                "ast-depth" => Box::new(egg::AstDepth),
+                "program" => self.inner.program_cost_function(),
+                _ => return Err(format!("Unknown cost function \"{}\"", cost_function)),
+            };
+        let eclass = eclass_id.as_deref().map(parse_eclass_id).transpose()?;
+
+        let extraction = self
+            .inner
+            .extract(eclass, cost_function.as_mut())
+            .map_err(|e| format!("{}", e))?;
+
+        let js_extraction = js_sys::Map::new();
+        js_extraction.set(
+            &js_sys::JsString::from("eclass"),
+            &js_sys::JsString::from(extraction.eclass.to_string()),
+        );
+        js_extraction.set(
+            &js_sys::JsString::from("term"),
+            &js_sys::JsString::from(extraction.term),
+        );
+        js_extraction.set(
+            &js_sys::JsString::from("cost"),
+            &js_sys::Number::from(extraction.cost),
+        );
+        Ok(js_extraction)
+    }
+
+    /// Export the current e-graph in the Graphviz DOT format, optionally
+    /// highlighting the changes made by the most recent step.
+    pub fn dump_graph(&self, highlight_last_step: bool) -> String {
+        self.inner.dump_graph(highlight_last_step)
+    }
+
+    /// Current e-graph, as a map from e-class ids to maps holding the e-class'
+    /// analysis `data` (a string) and its `enodes`. The latter map e-node ids
+    /// to maps of their `label` and `children` e-class ids.
+    pub fn current_graph(&self) -> js_sys::Map {
+        let graph = self.inner.current_graph();
+
+        let eclasses_map = js_sys::Map::new();
+        for (eclass_id, (data, enodes)) in graph.into_iter() {
+            let enode_map = js_sys::Map::new();
+            for (enode_id, (enode_label, children)) in enodes.into_iter() {
+                let enode_children: js_sys::Array =
+                    children.into_iter().map(js_sys::JsString::from).collect();
+
+                let enode = js_sys::Map::new();
+                enode.set(
+                    &js_sys::JsString::from("label"),
+                    &js_sys::JsString::from(enode_label),
+                );
+                enode.set(&js_sys::JsString::from("children"), &enode_children);
+
+                enode_map.set(&js_sys::Number::from(enode_id as f64), &enode);
+            }
+
+            let eclass = js_sys::Map::new();
+            eclass.set(
+                &js_sys::JsString::from("data"),
+                &js_sys::JsString::from(data),
+            );
+            eclass.set(&js_sys::JsString::from("enodes"), &enode_map);
+            eclasses_map.set(&js_sys::JsString::from(eclass_id), &eclass);
+        }
+        eclasses_map
+    }
+}
+
+fn error_message_to_js(msg: String) -> js_sys::Map {
+    let js_error = js_sys::Map::new();
+    js_error.set(
+        &js_sys::JsString::from("message"),
+        &js_sys::JsString::from(msg),
+    );
+    js_error
+}
+
+fn runtime_error_to_js(error: &EggvizRuntimeError) -> js_sys::Map {
+    let js_error = js_sys::Map::new();
+    js_error.set(
+        &js_sys::JsString::from("message"),
+        &js_sys::JsString::from(error.to_string()),
+    );
+
+    let diagnostics = match error {
+        EggvizRuntimeError::ParseError(e) => std::slice::from_ref(e),
+        EggvizRuntimeError::ParseErrors(diagnostics) => diagnostics.as_slice(),
+        _ => return js_error,
+    };
+    js_error.set(
+        &js_sys::JsString::from("diagnostics"),
+        &diagnostics
+            .iter()
+            .map(diagnostic_to_js)
+            .collect::<js_sys::Array>(),
+    );
+    js_error
+}
+
+fn diagnostic_to_js(diagnostic: &EggvizProgramParseError) -> js_sys::Map {
+    let js_diagnostic = js_sys::Map::new();
+    js_diagnostic.set(
+        &js_sys::JsString::from("severity"),
+        &js_sys::JsString::from(diagnostic.severity.to_string()),
+    );
+    js_diagnostic.set(
+        &js_sys::JsString::from("message"),
+        &js_sys::JsString::from(diagnostic.to_string()),
+    );
+
+    let (offset, length, line, column) = match diagnostic.context {
+        None => return js_diagnostic,
+        Some(EggvizProgramParseContext::Program {
+            offset,
+            length,
+            line,
+            column,
+        }) => {
+            js_diagnostic.set(
+                &js_sys::JsString::from("context"),
+                &js_sys::JsString::from("program"),
+            );
+            (offset, length, line, column)
+        }
+        Some(EggvizProgramParseContext::RewriteRule {
+            ref label,
+            side,
+            offset,
+            length,
+            line,
+            column,
+        }) => {
+            js_diagnostic.set(
+                &js_sys::JsString::from("context"),
+                &js_sys::JsString::from("rewrite-rule"),
+            );
+            js_diagnostic.set(
+                &js_sys::JsString::from("label"),
+                &js_sys::JsString::from(label.to_string()),
+            );
+            js_diagnostic.set(
+                &js_sys::JsString::from("side"),
+                &js_sys::JsString::from(side.to_string()),
+            );
+            (offset, length, line, column)
+        }
+    };
+    for (key, value) in [
+        ("offset", offset),
+        ("length", length),
+        ("line", line),
+        ("column", column),
+    ] {
+        js_diagnostic.set(
+            &js_sys::JsString::from(key),
+            &js_sys::Number::from(value as u32),
+        );
+    }
+    js_diagnostic
+}
+
+fn rewrite_rule_from_js(
+    idx: usize,
+    rule: &JsValue,
+) -> Result<(Option<String>, String, EggvizRewriteDirection, String), String> {
+    let field = |name: &str| {
+        js_sys::Reflect::get(rule, &JsValue::from_str(name))
+            .map_err(|_| format!("Rewrite rule {} is not an object", idx))
+            .map(|value| {
+                if value.is_undefined() || value.is_null() {
+                    None
+                } else {
+                    Some(value)
+                }
+            })
+    };
+    let string_field = |name: &str| {
+        field(name)?
+            .map(|value| {
+                value.as_string().ok_or_else(|| {
+                    format!(
+                        "Field \"{}\" of rewrite rule {} must be a string",
+                        name, idx
+                    )
+                })
+            })
+            .transpose()
+    };
+    let required_field = |name: &str| {
+        string_field(name)?
+            .ok_or_else(|| format!("Rewrite rule {} is missing field \"{}\"", idx, name))
+    };
+
+    let direction = match string_field("direction")? {
+        None => EggvizRewriteDirection::Forward,
+        Some(direction) => EggvizRewriteDirection::from_str(&direction)
+            .map_err(|_| format!("Unable to parse rewrite rule direction \"{}\"", direction))?,
+    };
+    Ok((
+        string_field("name")?,
+        required_field("lhs")?,
+        direction,
+        required_field("rhs")?,
+    ))
+}
+
+fn set_js_field(object: &js_sys::Object, name: &str, value: &JsValue) {
+    js_sys::Reflect::set(object, &JsValue::from_str(name), value)
+        .expect("Unable to set a field of a plain JS object");
+}
+
+/// Field of a JS object, where `undefined` and `null` count as missing.
+fn js_field(object: &JsValue, name: &str) -> Option<JsValue> {
+    js_sys::Reflect::get(object, &JsValue::from_str(name))
+        .ok()
+        .filter(|value| !value.is_undefined() && !value.is_null())
+}
+
+fn required_js_field(object: &JsValue, name: &str, what: &str) -> Result<JsValue, String> {
+    js_field(object, name).ok_or_else(|| format!("The {} is missing field \"{}\"", what, name))
+}
+
+fn string_js_field(object: &JsValue, name: &str, what: &str) -> Result<String, String> {
+    required_js_field(object, name, what)?
+        .as_string()
+        .ok_or_else(|| format!("Field \"{}\" of the {} must be a string", name, what))
+}
+
+fn number_js_field(object: &JsValue, name: &str, what: &str) -> Result<f64, String> {
+    required_js_field(object, name, what)?
+        .as_f64()
+        .ok_or_else(|| format!("Field \"{}\" of the {} must be a number", name, what))
+}
+
+fn array_js_field(object: &JsValue, name: &str, what: &str) -> Result<js_sys::Array, String> {
+    let value = required_js_field(object, name, what)?;
+    if js_sys::Array::is_array(&value) {
+        Ok(js_sys::Array::from(&value))
+    } else {
+        Err(format!(
+            "Field \"{}\" of the {} must be an array",
+            name, what
+        ))
+    }
+}
+
+fn session_to_js(session: &EggvizSession) -> js_sys::Object {
+    let rewrite_rules: js_sys::Array = session
+        .rewrite_rules
+        .iter()
+        .map(|(label, left, direction, right)| {
+            let js_rule = js_sys::Object::new();
+            if let Some(label) = label {
+                set_js_field(&js_rule, "name", &JsValue::from_str(label));
+            }
+            set_js_field(&js_rule, "lhs", &JsValue::from_str(left));
+            set_js_field(
+                &js_rule,
+                "direction",
+                &JsValue::from_str(&direction.to_string()),
+            );
+            set_js_field(&js_rule, "rhs", &JsValue::from_str(right));
+            js_rule
+        })
+        .collect();
+
+    let steps: js_sys::Array = session
+        .steps
+        .iter()
+        .map(|(step, applied_rules)| session_step_to_js(step, applied_rules))
+        .collect();
+
+    let js_session = js_sys::Object::new();
+    set_js_field(&js_session, "program", &JsValue::from_str(&session.program));
+    set_js_field(&js_session, "rewrite_rules", &rewrite_rules);
+    set_js_field(&js_session, "steps", &steps);
+    set_js_field(
+        &js_session,
+        "egraph_nodes",
+        &JsValue::from_f64(session.egraph_nodes as f64),
+    );
+    set_js_field(
+        &js_session,
+        "egraph_classes",
+        &JsValue::from_f64(session.egraph_classes as f64),
+    );
+    js_session
+}
+
+fn session_step_to_js(
+    step: &EggvizSessionStep,
+    applied_rules: &LinkedList<EggvizRewriteRuleLabel>,
+) -> js_sys::Object {
+    let js_step = js_sys::Object::new();
+    let step_type = match step {
+        EggvizSessionStep::RewriteRule(rule) => {
+            set_js_field(&js_step, "rule", &JsValue::from_str(&rule.to_string()));
+            "rewrite_rule"
+        }
+        EggvizSessionStep::RewriteRuleMatch(rule_match) => {
+            // The order of the substitution matters for selecting the match,
+            // so don't use an object here:
+            let substitution: js_sys::Array = rule_match
+                .substitution
+                .iter()
+                .map(|(var, id)| {
+                    js_sys::Array::of2(&JsValue::from_str(var), &JsValue::from_str(&id.to_string()))
+                })
+                .collect();
+            set_js_field(
+                &js_step,
+                "rule",
+                &JsValue::from_str(&rule_match.rule.to_string()),
+            );
+            set_js_field(
+                &js_step,
+                "eclass",
+                &JsValue::from_str(&rule_match.eclass.to_string()),
+            );
+            set_js_field(&js_step, "substitution", &substitution);
+            "rewrite_rule_match"
+        }
+        EggvizSessionStep::RewriteAuto => "rewrite_auto",
+        EggvizSessionStep::RunUntilSaturated {
+            iterations,
+            node_limit,
+        } => {
+            set_js_field(
+                &js_step,
+                "iterations",
+                &JsValue::from_f64(iterations.get() as f64),
+            );
+            set_js_field(
+                &js_step,
+                "node_limit",
+                &JsValue::from_f64(*node_limit as f64),
+            );
+            "run_until_saturated"
+        }
+        EggvizSessionStep::Union(a, b) => {
+            set_js_field(
+                &js_step,
+                "eclasses",
+                &js_sys::Array::of2(
+                    &JsValue::from_str(&a.to_string()),
+                    &JsValue::from_str(&b.to_string()),
+                ),
+            );
+            "union"
+        }
+        EggvizSessionStep::AddTerm(term) => {
+            set_js_field(&js_step, "term", &JsValue::from_str(term));
+            "add_term"
+        }
+    };
+    set_js_field(&js_step, "type", &JsValue::from_str(step_type));
+
+    let applied_rules: js_sys::Array = applied_rules
+        .iter()
+        .map(|rule| JsValue::from_str(&rule.to_string()))
+        .collect();
+    set_js_field(&js_step, "applied_rules", &applied_rules);
+    js_step
+}
+
+fn session_from_js(session: &JsValue) -> Result<EggvizSession, String> {
+    let what = "session";
+    let rewrite_rules = array_js_field(session, "rewrite_rules", what)?
+        .iter()
+        .enumerate()
+        .map(|(idx, rule)| rewrite_rule_from_js(idx, &rule))
+        .collect::<Result<Vec<_>, String>>()?;
+    let steps = array_js_field(session, "steps", what)?
+        .iter()
+        .enumerate()
+        .map(|(idx, step)| session_step_from_js(idx, &step))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(EggvizSession {
+        program: string_js_field(session, "program", what)?,
+        rewrite_rules,
+        steps,
+        egraph_nodes: number_js_field(session, "egraph_nodes", what)? as usize,
+        egraph_classes: number_js_field(session, "egraph_classes", what)? as usize,
+    })
+}
+
+fn session_step_from_js(
+    idx: usize,
+    step: &JsValue,
+) -> Result<(EggvizSessionStep, LinkedList<EggvizRewriteRuleLabel>), String> {
+    let what = format!("session step {}", idx + 1);
+    let parse_label = |label: String| {
+        EggvizRewriteRuleLabel::from_str(&label)
+            .map_err(|_| format!("Unable to parse rule label \"{}\"", label))
+    };
+    let session_step = match string_js_field(step, "type", &what)?.as_str() {
+        "rewrite_rule" => {
+            EggvizSessionStep::RewriteRule(parse_label(string_js_field(step, "rule", &what)?)?)
+        }
+        "rewrite_rule_match" => {
+            let substitution = array_js_field(step, "substitution", &what)?
+                .iter()
+                .map(|binding| {
+                    let binding = js_sys::Array::from(&binding);
+                    match (binding.get(0).as_string(), binding.get(1).as_string()) {
+                        (Some(var), Some(id)) => Ok((var, parse_eclass_id(&id)?)),
+                        _ => Err(format!(
+                            "Field \"substitution\" of the {} must hold pairs of strings",
+                            what
+                        )),
+                    }
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            EggvizSessionStep::RewriteRuleMatch(EggvizRuleMatch {
+                rule: parse_label(string_js_field(step, "rule", &what)?)?,
+                eclass: parse_eclass_id(&string_js_field(step, "eclass", &what)?)?,
+                substitution,
+            })
+        }
+        "rewrite_auto" => EggvizSessionStep::RewriteAuto,
+        "run_until_saturated" => EggvizSessionStep::RunUntilSaturated {
+            iterations: NonZeroUsize::new(number_js_field(step, "iterations", &what)? as usize)
+                .ok_or_else(|| format!("Iterations of the {} must be at least 1", what))?,
+            node_limit: number_js_field(step, "node_limit", &what)? as usize,
+        },
+        "union" => {
+            let eclasses = array_js_field(step, "eclasses", &what)?;
+            match (eclasses.get(0).as_string(), eclasses.get(1).as_string()) {
+                (Some(a), Some(b)) if eclasses.length() == 2 => {
+                    EggvizSessionStep::Union(parse_eclass_id(&a)?, parse_eclass_id(&b)?)
+                }
+                _ => {
+                    return Err(format!(
+                        "Field \"eclasses\" of the {} must hold two e-class ids",
+                        what
+                    ))
+                }
+            }
+        }
+        "add_term" => EggvizSessionStep::AddTerm(string_js_field(step, "term", &what)?),
+        step_type => return Err(format!("Unknown type \"{}\" of the {}", step_type, what)),
+    };
+
+    let applied_rules = array_js_field(step, "applied_rules", &what)?
+        .iter()
+        .map(|rule| {
+            rule.as_string()
+                .ok_or_else(|| format!("Field \"applied_rules\" of the {} must hold strings", what))
+                .and_then(parse_label)
+        })
+        .collect::<Result<LinkedList<_>, String>>()?;
+
+    Ok((session_step, applied_rules))
+}
+
+fn parse_eclass_id(eclass_id: &str) -> Result<egg::Id, String> {
+    eclass_id
+        .parse::<usize>()
+        .map(egg::Id::from)
+        .map_err(|_| format!("Unable to parse e-class id \"{}\"", eclass_id))
+}
+
+fn rule_match_to_js(rule_match: EggvizRuleMatch) -> js_sys::Map {
+    let substitution = js_sys::Map::new();
+    for (var, id) in rule_match.substitution.into_iter() {
+        substitution.set(
+            &js_sys::JsString::from(var),
+            &js_sys::JsString::from(id.to_string()),
+        );
+    }
+
+    let js_match = js_sys::Map::new();
+    js_match.set(
+        &js_sys::JsString::from("rule"),
+        &js_sys::JsString::from(rule_match.rule.to_string()),
+    );
+    js_match.set(
+        &js_sys::JsString::from("eclass"),
+        &js_sys::JsString::from(rule_match.eclass.to_string()),
+    );
+    js_match.set(&js_sys::JsString::from("substitution"), &substitution);
+    js_match
+}
+
+fn step_report_to_js(report: EggvizStepReport) -> js_sys::Map {
+    let applied_rules: js_sys::Array = report
+        .applied_rules
+        .into_iter()
+        .map(|rewrite_rule| js_sys::JsString::from(rewrite_rule.to_string()))
+        .collect();
+
+    let added_nodes: js_sys::Array = report
+        .added_nodes
+        .into_iter()
+        .map(|node| {
+            let children: js_sys::Array = node
+                .children
+                .into_iter()
+                .map(|id| js_sys::JsString::from(id.to_string()))
+                .collect();
+
+            let js_node = js_sys::Map::new();
+            js_node.set(
+                &js_sys::JsString::from("eclass"),
+                &js_sys::JsString::from(node.eclass.to_string()),
+            );
+            js_node.set(
+                &js_sys::JsString::from("label"),
+                &js_sys::JsString::from(node.function),
+            );
+            js_node.set(&js_sys::JsString::from("children"), &children);
+            js_node
+        })
+        .collect();
+
+    let added_classes: js_sys::Array = report
+        .added_classes
+        .into_iter()
+        .map(|id| js_sys::JsString::from(id.to_string()))
+        .collect();
+
+    let merged_classes = js_sys::Map::new();
+    for (prev_id, id) in report.merged_classes.into_iter() {
+        merged_classes.set(
+            &js_sys::JsString::from(prev_id.to_string()),
+            &js_sys::JsString::from(id.to_string()),
+        );
+    }
+
+    let match_count = report.matches.len();
+    let matches: js_sys::Array = report.matches.into_iter().map(rule_match_to_js).collect();
+
+    let js_report = js_sys::Map::new();
+    js_report.set(&js_sys::JsString::from("applied_rules"), &applied_rules);
+    js_report.set(&js_sys::JsString::from("added_nodes"), &added_nodes);
+    js_report.set(&js_sys::JsString::from("added_classes"), &added_classes);
+    js_report.set(&js_sys::JsString::from("merged_classes"), &merged_classes);
+    js_report.set(
+        &js_sys::JsString::from("match_count"),
+        &js_sys::Number::from(match_count as u32),
+    );
+    js_report.set(&js_sys::JsString::from("matches"), &matches);
+    js_report
+}
+
+fn explanation_rule_to_js(js_map: &js_sys::Map, rule: Option<EggvizExplanationRule>) {
+    if let Some(rule) = rule {
+        js_map.set(
+            &js_sys::JsString::from("rule"),
+            &js_sys::JsString::from(rule.label.to_string()),
+        );
+        js_map.set(
+            &js_sys::JsString::from("forward"),
+            &js_sys::Boolean::from(rule.forward),
+        );
+    }
+}
+
+fn explanation_tree_to_js(tree: EggvizExplanationTree) -> js_sys::Map {
+    let child_proofs: js_sys::Array = tree
+        .child_proofs
+        .into_iter()
+        .map(|child_proof| {
+            child_proof
+                .into_iter()
+                .map(explanation_tree_to_js)
+                .collect::<js_sys::Array>()
+        })
+        .collect();
+
+    let js_tree = js_sys::Map::new();
+    js_tree.set(
+        &js_sys::JsString::from("label"),
+        &js_sys::JsString::from(tree.function),
+    );
+    explanation_rule_to_js(&js_tree, tree.rule);
+    js_tree.set(&js_sys::JsString::from("child_proofs"), &child_proofs);
+    js_tree
+}
+
+fn explanation_to_js(explanation: EggvizExplanation) -> js_sys::Map {
+    let flat: js_sys::Array = explanation
+        .flat
+        .into_iter()
+        .map(|step| {
+            let js_step = js_sys::Map::new();
+            js_step.set(
+                &js_sys::JsString::from("term"),
+                &js_sys::JsString::from(step.term),
+            );
+            explanation_rule_to_js(&js_step, step.rule);
+            js_step
+        })
+        .collect();
+
+    let tree: js_sys::Array = explanation
+        .tree
+        .into_iter()
+        .map(explanation_tree_to_js)
+        .collect();
+
+    let js_explanation = js_sys::Map::new();
+    js_explanation.set(&js_sys::JsString::from("flat"), &flat);
+    js_explanation.set(&js_sys::JsString::from("tree"), &tree);
+    js_explanation
+}
+
+#[wasm_bindgen(start)]
+pub fn startup() -> Result<(), JsValue> {
+    // This provides better error messages.
+    console_error_panic_hook::set_once();
+
+    console::log_1(&JsValue::from_str("eggviz WASM module initialized!"));
+
+    Ok(())
+}
@@ -0,0 +1,23 @@
+//! Runs every example script in the `scripts` directory, such that broken
+//! examples are caught by `cargo test`.
+
+use eggviz::lispylang::Program;
+use eggviz::script::run_script_dir;
+
+#[test]
+fn scripts() {
+    let results = run_script_dir::<Program>(concat!(env!("CARGO_MANIFEST_DIR"), "/scripts"))
+        .expect("Unable to run the scripts");
+    assert!(!results.is_empty(), "No scripts found");
+
+    let failures: Vec<String> = results
+        .iter()
+        .filter_map(|(path, result)| {
+            result
+                .as_ref()
+                .err()
+                .map(|e| format!("{} failed at {}", path.display(), e))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}